@@ -0,0 +1,292 @@
+//! Arb-detektion - køb/sælg begge ben når summen afviger fra den garanterede $1 udbetaling.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::book::Level;
+use crate::orderbook::{ConnectionStatus, LegHealth, OrderbookState, Side};
+
+/// Retning af en arb
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArbKind {
+    /// `up_ask + down_ask < 1` - køb begge ben billigere end udbetalingen
+    BuyBoth,
+    /// `up_bid + down_bid > 1` - sælg begge ben dyrere end udbetalingen
+    SellBoth,
+}
+
+/// En arb-mulighed på top-of-book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArbOpportunity {
+    pub kind: ArbKind,
+    /// UP-pris der handles på (ask ved køb, bid ved salg)
+    pub up_price: Decimal,
+    /// DOWN-pris der handles på
+    pub down_price: Decimal,
+    /// Edge pr. par i cents
+    pub edge_cents: Decimal,
+    /// Maks. antal par der kan handles til de priser (mindste size af de to ben)
+    pub size: Decimal,
+}
+
+/// Find en arb i state - kun på rigtige bogdata, aldrig på afledte komplementer,
+/// og kun når begge ben er handelbare (se `OrderbookState::is_tradeable`)
+pub fn detect(state: &OrderbookState) -> Option<ArbOpportunity> {
+    if !state.is_tradeable() {
+        return None;
+    }
+    buy_both(state).or_else(|| sell_both(state))
+}
+
+fn buy_both(state: &OrderbookState) -> Option<ArbOpportunity> {
+    if state.derived.up_ask || state.derived.down_ask {
+        return None;
+    }
+    let up_price = state.up_ask_price?;
+    let down_price = state.down_ask_price?;
+    let edge = Decimal::ONE - (up_price + down_price);
+    opportunity(ArbKind::BuyBoth, up_price, down_price, edge, state.up_ask_size?, state.down_ask_size?)
+}
+
+fn sell_both(state: &OrderbookState) -> Option<ArbOpportunity> {
+    if state.derived.up_bid || state.derived.down_bid {
+        return None;
+    }
+    let up_price = state.up_bid_price?;
+    let down_price = state.down_bid_price?;
+    let edge = (up_price + down_price) - Decimal::ONE;
+    opportunity(ArbKind::SellBoth, up_price, down_price, edge, state.up_bid_size?, state.down_bid_size?)
+}
+
+fn opportunity(
+    kind: ArbKind,
+    up_price: Decimal,
+    down_price: Decimal,
+    edge: Decimal,
+    up_size: Decimal,
+    down_size: Decimal,
+) -> Option<ArbOpportunity> {
+    let size = up_size.min(down_size);
+    if edge <= Decimal::ZERO || size <= Decimal::ZERO {
+        return None;
+    }
+    Some(ArbOpportunity {
+        kind,
+        up_price,
+        down_price,
+        edge_cents: edge * Decimal::ONE_HUNDRED,
+        size,
+    })
+}
+
+/// Værn der pauser arb-signaler når data ikke er til at stole på
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Maks. tid siden seneste besked på det mest stille ben (None = ingen grænse).
+    /// Et stille marked med uændrede priser er ikke forældet, så længe beskederne kommer
+    pub max_age: Option<Duration>,
+    /// Større edge end dette (cents) er næsten altid en datafejl, ikke gratis penge
+    pub max_edge_cents: Option<Decimal>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            max_age: Some(Duration::from_secs(60)),
+            max_edge_cents: Some(Decimal::TEN),
+        }
+    }
+}
+
+/// Hvorfor breakeren har pauset signalerne
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerTrip {
+    /// Forbindelsen er ikke oppe (forbinder eller genforbinder)
+    NotConnected(ConnectionStatus),
+    /// Benet er slået fra efter gentagne dårlige beskeder
+    LegDisabled(Side),
+    /// Mindst én af de fire top-of-book priser mangler
+    MissingSide,
+    /// Mindst ét ben har ikke sendt en besked inden for `max_age`
+    StaleData { age_ms: i64 },
+    /// Benets spread er over `OrderbookConfig::max_spread`
+    WideSpread(Side),
+    /// Edge over `max_edge_cents`
+    ImplausibleEdge { edge_cents: Decimal },
+}
+
+impl std::fmt::Display for BreakerTrip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreakerTrip::NotConnected(status) => write!(f, "ikke forbundet ({:?})", status),
+            BreakerTrip::LegDisabled(side) => write!(f, "{:?}-benet er slået fra", side),
+            BreakerTrip::MissingSide => write!(f, "en side mangler"),
+            BreakerTrip::StaleData { age_ms } => write!(f, "forældet data ({} ms)", age_ms),
+            BreakerTrip::WideSpread(side) => write!(f, "for bred spread på {:?}", side),
+            BreakerTrip::ImplausibleEdge { edge_cents } => write!(f, "usandsynlig edge ({:.1}c)", edge_cents),
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Grunden til at signaler er pauset, None hvis data kan stoles på
+    pub fn check(&self, state: &OrderbookState, status: ConnectionStatus, now_ms: i64) -> Option<BreakerTrip> {
+        if matches!(
+            status,
+            ConnectionStatus::Connecting | ConnectionStatus::Disconnected | ConnectionStatus::Reconnecting
+        ) {
+            return Some(BreakerTrip::NotConnected(status));
+        }
+        for (side, health) in [(Side::Up, state.up_health), (Side::Down, state.down_health)] {
+            if let LegHealth::Disabled { .. } = health {
+                return Some(BreakerTrip::LegDisabled(side));
+            }
+        }
+        let prices = [state.up_bid_price, state.up_ask_price, state.down_bid_price, state.down_ask_price];
+        if prices.iter().any(Option::is_none) {
+            return Some(BreakerTrip::MissingSide);
+        }
+        let age_ms = state.message_age_ms(now_ms);
+        if self.max_age.is_some_and(|max| age_ms > max.as_millis() as i64) {
+            return Some(BreakerTrip::StaleData { age_ms });
+        }
+        for (side, wide) in [(Side::Up, state.up_wide_spread), (Side::Down, state.down_wide_spread)] {
+            if wide {
+                return Some(BreakerTrip::WideSpread(side));
+            }
+        }
+        let edge_cents = detect(state)?.edge_cents;
+        if self.max_edge_cents.is_some_and(|max| edge_cents > max) {
+            return Some(BreakerTrip::ImplausibleEdge { edge_cents });
+        }
+        None
+    }
+
+    /// `detect` bag breakeren - None både uden arb og når signaler er pauset
+    pub fn detect(&self, state: &OrderbookState, status: ConnectionStatus, now_ms: i64) -> Option<ArbOpportunity> {
+        match self.check(state, status, now_ms) {
+            Some(_) => None,
+            None => detect(state),
+        }
+    }
+}
+
+/// Ét trin i en arb over flere niveauer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rung {
+    /// Antal par handlet til og med dette trin
+    pub cumulative_size: Decimal,
+    /// Gennemsnitlig pris pr. par (UP + DOWN) over alle par indtil nu
+    pub average_combined_cost: Decimal,
+}
+
+/// Gå begge bens dybde igennem i takt og saml par så længe det marginale par
+/// stadig er et arb (`up + down < 1` ved køb, `> 1` ved salg).
+///
+/// `up` og `down` er niveauerne der handles mod - asks ved `BuyBoth`, bids ved
+/// `SellBoth` - med bedste niveau først (som `OrderbookHandle::get_depth`).
+/// Ét trin pr. gang et af niveauerne er brugt op; tom hvis der intet arb er.
+pub fn rungs(kind: ArbKind, up: &[Level], down: &[Level]) -> Vec<Rung> {
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let (mut up_left, mut down_left) = (Decimal::ZERO, Decimal::ZERO);
+    let (mut cumulative, mut cost) = (Decimal::ZERO, Decimal::ZERO);
+
+    while i < up.len() && j < down.len() {
+        if up_left.is_zero() {
+            up_left = up[i].size;
+        }
+        if down_left.is_zero() {
+            down_left = down[j].size;
+        }
+        let pair = up[i].price + down[j].price;
+        let is_arb = match kind {
+            ArbKind::BuyBoth => pair < Decimal::ONE,
+            ArbKind::SellBoth => pair > Decimal::ONE,
+        };
+        if !is_arb {
+            break;
+        }
+
+        let qty = up_left.min(down_left);
+        cumulative += qty;
+        cost += qty * pair;
+        up_left -= qty;
+        down_left -= qty;
+        if up_left.is_zero() {
+            i += 1;
+        }
+        if down_left.is_zero() {
+            j += 1;
+        }
+        if qty > Decimal::ZERO {
+            out.push(Rung {
+                cumulative_size: cumulative,
+                average_combined_cost: cost / cumulative,
+            });
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    /// Begge ben med priser, uden arb, senest ændret ved 1000 ms
+    fn quiet_state(up_message_ms: i64, down_message_ms: i64) -> OrderbookState {
+        OrderbookState {
+            up_bid_price: Some(dec("0.40")),
+            up_ask_price: Some(dec("0.42")),
+            down_bid_price: Some(dec("0.57")),
+            down_ask_price: Some(dec("0.59")),
+            last_update_ms: 1_000,
+            up_last_message_ms: up_message_ms,
+            down_last_message_ms: down_message_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unchanged_prices_are_not_stale_while_both_legs_send_messages() {
+        let breaker = CircuitBreaker::default();
+        let state = quiet_state(119_000, 120_000);
+        assert_eq!(breaker.check(&state, ConnectionStatus::BooksLive, 120_000), None);
+    }
+
+    #[test]
+    fn a_silent_leg_trips_the_breaker_from_its_own_last_message() {
+        let breaker = CircuitBreaker::default();
+        let state = quiet_state(119_000, 50_000);
+        let trip = breaker.check(&state, ConnectionStatus::BooksLive, 120_000);
+        assert_eq!(trip, Some(BreakerTrip::StaleData { age_ms: 70_000 }));
+    }
+
+    #[test]
+    fn a_wide_spread_trips_the_breaker_for_that_leg() {
+        let breaker = CircuitBreaker::default();
+        let mut state = quiet_state(120_000, 120_000);
+        state.down_wide_spread = true;
+        let trip = breaker.check(&state, ConnectionStatus::BooksLive, 120_000);
+        assert_eq!(trip, Some(BreakerTrip::WideSpread(Side::Down)));
+    }
+
+    #[test]
+    fn a_disabled_leg_trips_the_breaker_even_with_prices() {
+        let breaker = CircuitBreaker::default();
+        let mut state = quiet_state(120_000, 120_000);
+        state.up_health = LegHealth::Disabled { until_ms: 150_000 };
+        let trip = breaker.check(&state, ConnectionStatus::BooksLive, 120_000);
+        assert_eq!(trip, Some(BreakerTrip::LegDisabled(Side::Up)));
+
+        // Et Suspect-ben handles stadig
+        state.up_health = LegHealth::Suspect { consecutive_bad: 1 };
+        assert_eq!(breaker.check(&state, ConnectionStatus::BooksLive, 120_000), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Revisionslog for arb-signaler - én JSON-linje pr. `FeedEvent::ArbDetected`, kun tilføjelse.
+//!
+//! Adskilt fra tracing-logs og fra optagelsen (`recorder`). Hver linje skrives og
+//! synkroniseres til disk før signalet sendes videre, så den seneste beslutning
+//! overlever et nedbrud.
+//!
+//! Format pr. linje: `ts_ms`, `token_up`, `token_down`, `kind`, `edge_cents`,
+//! `up_price`, `down_price`, `size` og vagternes tilstand - `status`, `age_ms`,
+//! `up_health`/`down_health` og `up_wide_spread`/`down_wide_spread`.
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::arb::{ArbKind, ArbOpportunity};
+use crate::orderbook::{ConnectionStatus, LegHealth, OrderbookState};
+
+/// Én linje i revisionsloggen
+#[derive(Serialize)]
+struct Entry<'a> {
+    ts_ms: i64,
+    token_up: &'a str,
+    token_down: &'a str,
+    kind: ArbKind,
+    edge_cents: Decimal,
+    up_price: Decimal,
+    down_price: Decimal,
+    size: Decimal,
+    status: ConnectionStatus,
+    /// Alder af seneste state-ændring da signalet blev givet
+    age_ms: Option<i64>,
+    up_health: LegHealth,
+    down_health: LegHealth,
+    up_wide_spread: bool,
+    down_wide_spread: bool,
+}
+
+/// Åben revisionslog for ét marked
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    file: File,
+    token_up: String,
+    token_down: String,
+}
+
+impl AuditLog {
+    /// Åbn `path` til tilføjelse - filen oprettes hvis den ikke findes
+    pub(crate) fn open(path: &Path, token_up: &str, token_down: &str) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            token_up: token_up.to_string(),
+            token_down: token_down.to_string(),
+        })
+    }
+
+    /// Skriv signalet med `state` og `status` som de var ved beslutningen
+    pub(crate) fn append(
+        &mut self,
+        opportunity: &ArbOpportunity,
+        state: &OrderbookState,
+        status: ConnectionStatus,
+        now_ms: i64,
+    ) -> io::Result<()> {
+        let entry = Entry {
+            ts_ms: now_ms,
+            token_up: &self.token_up,
+            token_down: &self.token_down,
+            kind: opportunity.kind,
+            edge_cents: opportunity.edge_cents,
+            up_price: opportunity.up_price,
+            down_price: opportunity.down_price,
+            size: opportunity.size,
+            status,
+            age_ms: state.age_ms(now_ms),
+            up_health: state.up_health,
+            down_health: state.down_health,
+            up_wide_spread: state.up_wide_spread,
+            down_wide_spread: state.down_wide_spread,
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        // Én skrivning pr. linje, så linjer fra et nedbrud aldrig blandes
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arb;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("audit-{}-{}.jsonl", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn lines(path: &Path) -> Vec<Value> {
+        let text = std::fs::read_to_string(path).unwrap();
+        text.lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn signals_are_appended_with_prices_and_guard_states() {
+        let path = temp_path("fields");
+        let state = OrderbookState {
+            up_ask_price: Some(dec("0.45")),
+            up_ask_size: Some(dec("10")),
+            down_ask_price: Some(dec("0.52")),
+            down_ask_size: Some(dec("4")),
+            up_bid_price: Some(dec("0.44")),
+            up_bid_size: Some(dec("1")),
+            down_bid_price: Some(dec("0.51")),
+            down_bid_size: Some(dec("1")),
+            last_update_ms: 1_000,
+            ..Default::default()
+        };
+        let opportunity = arb::detect(&state).unwrap();
+
+        let mut log = AuditLog::open(&path, "up", "down").unwrap();
+        log.append(&opportunity, &state, ConnectionStatus::BooksLive, 1_250).unwrap();
+        drop(log);
+        // En ny åbning tilføjer i stedet for at overskrive
+        let mut log = AuditLog::open(&path, "up", "down").unwrap();
+        log.append(&opportunity, &state, ConnectionStatus::BooksLive, 2_000).unwrap();
+
+        let lines = lines(&path);
+        assert_eq!(lines.len(), 2);
+        let line = &lines[0];
+        assert_eq!(line["ts_ms"], 1_250);
+        assert_eq!((&line["token_up"], &line["token_down"]), (&Value::from("up"), &Value::from("down")));
+        assert_eq!(line["kind"], "buy_both");
+        assert_eq!(line["edge_cents"], "3.00");
+        assert_eq!((&line["up_price"], &line["down_price"]), (&Value::from("0.45"), &Value::from("0.52")));
+        assert_eq!(line["size"], "4");
+        assert_eq!(line["status"], "books_live");
+        assert_eq!(line["age_ms"], 250);
+        assert_eq!(line["up_health"]["state"], "healthy");
+        assert_eq!((&line["up_wide_spread"], &line["down_wide_spread"]), (&Value::from(false), &Value::from(false)));
+        assert_eq!(lines[1]["ts_ms"], 2_000);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! Fuld prisniveau-bog pr. token - snapshots erstatter, `price_change` deltas opdaterer.
+
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Prisniveau - Decimal bevarer feedets præcision (`0.370` vises som `0.370`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+/// Sorterede bid- og ask-niveauer for ét token, nøglet på pris
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LevelBook {
+    bids: BTreeMap<Decimal, Level>,
+    asks: BTreeMap<Decimal, Level>,
+}
+
+/// Dårlig data i en book- eller price_change-besked
+#[derive(Debug)]
+pub(crate) struct BadLevel;
+
+impl LevelBook {
+    /// Erstat hele bogen med et snapshot (`bids`/`asks`, evt. `buys`/`sells`)
+    pub fn apply_snapshot(&mut self, data: &serde_json::Value) -> Result<(), BadLevel> {
+        let bids = levels(data, "bids", "buys")?;
+        let asks = levels(data, "asks", "sells")?;
+        self.bids = bids;
+        self.asks = asks;
+        Ok(())
+    }
+
+    /// Anvend én delta - size 0 fjerner niveauet
+    pub fn apply_change(&mut self, change: &serde_json::Value) -> Result<(), BadLevel> {
+        let level = parse_level(change)?;
+        let side = match change.get("side").and_then(|v| v.as_str()) {
+            Some(s) if s.eq_ignore_ascii_case("buy") || s.eq_ignore_ascii_case("bid") => &mut self.bids,
+            Some(s) if s.eq_ignore_ascii_case("sell") || s.eq_ignore_ascii_case("ask") => &mut self.asks,
+            _ => return Err(BadLevel),
+        };
+
+        if level.size.is_zero() {
+            side.remove(&level.price);
+        } else {
+            side.insert(level.price, level);
+        }
+        Ok(())
+    }
+
+    /// Højeste bid
+    pub fn best_bid(&self) -> Option<&Level> {
+        self.bids.values().next_back()
+    }
+
+    /// Laveste ask
+    pub fn best_ask(&self) -> Option<&Level> {
+        self.asks.values().next()
+    }
+
+    /// Ingen niveauer på nogen side
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    /// De `n` bedste bids, bedste først
+    pub fn top_bids(&self, n: usize) -> Vec<Level> {
+        self.bids.values().rev().take(n).copied().collect()
+    }
+
+    /// De `n` bedste asks, bedste først
+    pub fn top_asks(&self, n: usize) -> Vec<Level> {
+        self.asks.values().take(n).copied().collect()
+    }
+
+    /// Krydset bog (bedste bid >= bedste ask) er ikke til at stole på
+    pub fn is_crossed(&self) -> bool {
+        match (self.bids.keys().next_back(), self.asks.keys().next()) {
+            (Some(bid), Some(ask)) => bid >= ask,
+            _ => false,
+        }
+    }
+}
+
+fn levels(
+    data: &serde_json::Value,
+    key: &str,
+    alt_key: &str,
+) -> Result<BTreeMap<Decimal, Level>, BadLevel> {
+    let Some(items) = data.get(key).or_else(|| data.get(alt_key)).and_then(|v| v.as_array()) else {
+        return Ok(BTreeMap::new());
+    };
+
+    let mut map = BTreeMap::new();
+    for item in items {
+        let level = parse_level(item)?;
+        if !level.size.is_zero() {
+            map.insert(level.price, level);
+        }
+    }
+    Ok(map)
+}
+
+/// Parse et niveau ved ingest - priser skal ligge i [0, 1] og sizes være ikke-negative
+fn parse_level(v: &serde_json::Value) -> Result<Level, BadLevel> {
+    let price = decimal_field(v, "price")?;
+    let size = decimal_field(v, "size")?;
+    if price < Decimal::ZERO || price > Decimal::ONE || size < Decimal::ZERO {
+        return Err(BadLevel);
+    }
+    Ok(Level { price, size })
+}
+
+fn decimal_field(v: &serde_json::Value, key: &str) -> Result<Decimal, BadLevel> {
+    let raw = v.get(key).and_then(|p| p.as_str()).ok_or(BadLevel)?;
+    raw.parse::<Decimal>().map_err(|_| BadLevel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arb::{self, ArbKind};
+    use serde_json::json;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn sizes_parse_without_precision_loss() {
+        let mut book = LevelBook::default();
+        let level = json!({ "price": "0.370", "size": "1234567.891234567" });
+        book.apply_snapshot(&json!({ "bids": [level], "asks": [] })).unwrap();
+
+        let best = book.best_bid().unwrap();
+        assert_eq!(best.size, dec("1234567.891234567"));
+        assert_eq!(best.price.to_string(), "0.370");
+    }
+
+    #[test]
+    fn aggregating_many_fractional_levels_is_exact() {
+        // 0.1 + 0.2 er præcis 0.3 - i f64 er det 0.30000000000000004
+        let asks = [("0.40", "0.1"), ("0.41", "0.2")].map(|(p, s)| json!({ "price": p, "size": s }));
+        let mut book = LevelBook::default();
+        book.apply_snapshot(&json!({ "bids": [], "asks": asks })).unwrap();
+        let total: Decimal = book.top_asks(usize::MAX).iter().map(|l| l.size).sum();
+        assert_eq!(total, dec("0.3"));
+
+        // 1000 niveauer á 0.1 parret mod ét dybt niveau giver præcis 100 par
+        let up: Vec<Level> = (0..1000)
+            .map(|i| Level { price: dec("0.30") + Decimal::new(i, 4), size: dec("0.1") })
+            .collect();
+        let down = [Level { price: dec("0.50"), size: dec("500") }];
+        let rungs = arb::rungs(ArbKind::BuyBoth, &up, &down);
+        assert_eq!(rungs.len(), 1000);
+        assert_eq!(rungs.last().unwrap().cumulative_size, dec("100"));
+    }
+}
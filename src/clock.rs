@@ -0,0 +1,65 @@
+//! Vægur-tid bag et trait, så TTL, udløb og staleness kan styres deterministisk.
+//!
+//! Tidspunkter - tidsstempler, aldre, TTL og udløb - læses via en `Arc<dyn Clock>`,
+//! typisk `OrderbookConfig::clock`; standard er `SystemClock`. `MockClock` står stille
+//! indtil den flyttes med `advance`/`set_ms`. Ventetider og intervaller (timeouts,
+//! backoff, staleness, første-data-fristen) måles med tokios monotone ur og følger
+//! ikke `Clock` - i tests styres de med `tokio::time::pause`.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Kilde til vægur-tid
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Unix-tid i millisekunder
+    fn now_ms(&self) -> i64;
+
+    /// Unix-tid i hele sekunder
+    fn now_secs(&self) -> i64 {
+        self.now_ms().div_euclid(1000)
+    }
+}
+
+/// Rigtig tid via `chrono::Utc::now()`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+}
+
+/// Manuelt styret tid - kloner deler samme tidspunkt
+#[derive(Debug, Clone, Default)]
+pub struct MockClock {
+    ms: Arc<AtomicI64>,
+}
+
+impl MockClock {
+    pub fn new(start_ms: i64) -> Self {
+        Self {
+            ms: Arc::new(AtomicI64::new(start_ms)),
+        }
+    }
+
+    pub fn set_ms(&self, ms: i64) {
+        self.ms.store(ms, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.ms.fetch_add(by.as_millis() as i64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> i64 {
+        self.ms.load(Ordering::SeqCst)
+    }
+}
+
+/// Standard-uret
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
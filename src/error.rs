@@ -0,0 +1,57 @@
+//! Fejl fra orderbook-laget - leveres som `FeedEvent::Error`, så forbrugeren
+//! selv bestemmer præsentationen.
+
+/// Fejl i orderbook-laget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// WebSocket-forbindelsen kunne ikke oprettes
+    Connect(String),
+    /// Subscribe-frame kunne ikke sendes
+    Subscribe(String),
+    /// En besked kunne ikke parses som JSON
+    Parse(String),
+    /// Forbindelsen lukkede før begge bøger var modtaget
+    ClosedBeforeReady(String),
+    /// Forbindelsen blev tabt efter bøgerne var live
+    ConnectionLost(String),
+    /// Optagelsen kunne ikke læses (replay)
+    Replay(String),
+    /// Bøgerne nåede ikke frem inden for fristen
+    Timeout(String),
+    /// Serveren lukkede pænt med en Close-frame (fx 1000 normal, 1008 policy, 1013 prøv senere)
+    ServerClosed { code: u16, reason: String },
+}
+
+impl Error {
+    /// Serveren bad os holde igen - genforbind med lang backoff
+    pub fn is_throttled(&self) -> bool {
+        match self {
+            Error::ServerClosed { code, reason } => {
+                matches!(code, 1008 | 1013) || reason.to_ascii_lowercase().contains("rate")
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Connect(e) => write!(f, "WS connect fejlede: {}", e),
+            Error::Subscribe(e) => write!(f, "subscribe fejlede: {}", e),
+            Error::Parse(e) => write!(f, "ulæselig besked: {}", e),
+            Error::ClosedBeforeReady(e) => write!(f, "forbindelsen lukkede før bøgerne var klar: {}", e),
+            Error::ConnectionLost(e) => write!(f, "WS forbindelse tabt: {}", e),
+            Error::Replay(e) => write!(f, "replay fejlede: {}", e),
+            Error::Timeout(e) => write!(f, "timeout: {}", e),
+            Error::ServerClosed { code, reason } if reason.is_empty() => {
+                write!(f, "serveren lukkede forbindelsen ({})", code)
+            }
+            Error::ServerClosed { code, reason } => {
+                write!(f, "serveren lukkede forbindelsen ({}): {}", code, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,18 @@
+//! Polymarket BTC Up/Down orderbog-monitor.
+
+pub mod arb;
+mod audit;
+mod book;
+pub mod clock;
+pub mod error;
+pub mod market;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod orderbook;
+pub mod paper;
+pub mod recorder;
+pub mod render;
+pub mod session;
+pub mod sizing;
+pub mod stats;
+pub mod user;
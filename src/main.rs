@@ -1,52 +1,242 @@
-mod market;
-mod orderbook;
-
-use std::io::Write;
+use polymarket::render::{self, JsonLines, LineLog, Renderer, TerminalLine};
+use polymarket::session::{self, SessionEvent};
+use polymarket::orderbook::Side;
+use polymarket::{market, orderbook, paper};
+use rust_decimal::Decimal;
+use std::io::IsTerminal;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
 
 #[tokio::main]
 async fn main() {
-    let client = reqwest::Client::builder().tcp_nodelay(true).build().unwrap();
+    let opts = Options::from_args();
+
+    // Biblioteket udsender kun events - binæren vælger niveau og format.
+    // `RUST_LOG` vinder over `--log-level`.
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&opts.log_level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let client = match reqwest::Client::builder().tcp_nodelay(true).build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Kunne ikke oprette HTTP-klient: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let config = session::SessionConfig {
+        orderbook: orderbook::OrderbookConfig {
+            max_spread: Some(Decimal::new(10, 2)),
+            max_bad_messages: Some(5),
+            max_staleness: Some(Duration::from_secs(60)),
+            record_path: opts.record_path.clone(),
+            audit_path: opts.audit_path.clone(),
+            seed_client: Some(client.clone()),
+            ..Default::default()
+        },
+        post_expiry_observe_secs: opts.post_expiry_observe_secs,
+        state_path: opts.state_path.clone(),
+        ..Default::default()
+    };
+    let clock = config.orderbook.clock.clone();
 
-    // Market discovery
-    let Some(m) = market::find_active(&client).await else {
-        println!("Intet aktivt marked fundet");
-        return;
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = opts.metrics_addr {
+        let clock = clock.clone();
+        tokio::spawn(async move {
+            if let Err(e) = polymarket::metrics::serve(addr, clock).await {
+                eprintln!("Metrics-server på {} stoppede: {}", addr, e);
+            }
+        });
+    }
+
+    // Ét marked ad gangen - ved udløb rulles videre til næste slot.
+    // Første discovery prøver at genoptage markedet fra før en genstart.
+    let mut resume_from = opts.state_path.clone();
+    let mut trader = opts.paper_max_pairs.map(paper::PaperTrader::new);
+    // Udfald slås op i baggrunden efter udløb og afleveres ved næste event
+    let (resolved_tx, resolved_rx) = std::sync::mpsc::channel::<(market::Market, Option<market::Outcome>)>();
+    // Linjen overskrives kun på en terminal - ellers én linje pr. update.
+    // Med `--json` er stdout forbeholdt JSON, og øvrige meddelelser går til stderr
+    let json = opts.json;
+    let note = |msg: String| if json { eprintln!("{}", msg) } else { println!("{}", msg) };
+    let mut renderer: Box<dyn Renderer> = if json {
+        Box::new(JsonLines { clock: clock.clone() })
+    } else if std::io::stdout().is_terminal() {
+        Box::new(TerminalLine { verbose: opts.verbose })
+    } else {
+        Box::new(LineLog { verbose: opts.verbose, clock: clock.clone() })
     };
+    // `--duration`/`--max-updates` stopper kørslen; uden dem køres der til processen dræbes
+    let deadline = opts.duration.map(|d| Instant::now() + d);
+    let mut updates = 0u64;
+    let mut last_update_ms = 0;
+    let mut last_line = None;
+    session::run_session(
+        || {
+            let resume_from = resume_from.take();
+            let client = &client;
+            async move {
+                if let Some(path) = &resume_from {
+                    let params = market::DiscoveryParams::default();
+                    if let Some(market) = market::resume(client, &params, path).await {
+                        return Ok(market);
+                    }
+                }
+                market::find_active(client).await
+            }
+        },
+        &config,
+        |event| {
+            while let Ok((m, outcome)) = resolved_rx.try_recv() {
+                let Some(outcome) = outcome else {
+                    note(format!("\n{}: udfald ikke kendt", m.title));
+                    continue;
+                };
+                note(format!("\n{}: {} vandt", m.title, outcome.name));
+                // Resten af det vindende ben udbetales nu; parrene er allerede afregnet
+                if let Some(trader) = trader.as_mut() {
+                    let winner = if outcome.token_id == m.token_up { Side::Up } else { Side::Down };
+                    let payout = trader.settle(&m.slug, Some(winner));
+                    note(format!("[papir] Afregnet {} - PnL {}", payout, trader.ledger().pnl()));
+                }
+            }
+            match event {
+                SessionEvent::DiscoveryFailed(e) => {
+                    note(format!("Intet aktivt marked fundet: {} - prøver igen", e));
+                }
+                SessionEvent::Started(m) => note(format!("\n{}", m.title)),
+                SessionEvent::FeedError(e) => eprintln!("\n[feed] {}", e),
+                SessionEvent::Arb { market, opportunity, rungs } => {
+                    if let Some(trader) = trader.as_mut() {
+                        let now_ms = clock.now_ms();
+                        if let Some(t) = trader.on_arb(&market.slug, opportunity, rungs, now_ms) {
+                            note(format!(
+                                "\n[papir] {:?} {} par á {} - PnL {}",
+                                t.kind, t.pairs, t.average_combined_cost, trader.ledger().pnl()
+                            ));
+                        }
+                    }
+                }
+                SessionEvent::Expired(m) => {
+                    note("\nMarked udløbet!".to_string());
+                    // Par udbetaler $1 uanset udfald - ubalancen afregnes når vinderen kendes
+                    if let Some(trader) = trader.as_mut() {
+                        let payout = trader.settle(&m.slug, None);
+                        note(format!("[papir] Afregnet {} - PnL {}", payout, trader.ledger().pnl()));
+                    }
+                    let (client, market, tx) = (client.clone(), m.clone(), resolved_tx.clone());
+                    tokio::spawn(async move {
+                        let outcome = market::resolve_outcome(&client, &market).await;
+                        let _ = tx.send((market, outcome));
+                    });
+                }
+                SessionEvent::NoData(_) => note("\nIngen data fra markedet - venter på næste slot".to_string()),
+                SessionEvent::Ended(_) => {
+                    if opts.post_expiry_observe_secs > 0 {
+                        note("\nObservation efter udløb afsluttet".to_string());
+                    }
+                }
+                SessionEvent::Update { market, state, status, ttl, expired } => {
+                    renderer.render(market, state, status, ttl, expired);
+                    // TTL-ticks uden ny state tæller ikke som opdateringer
+                    if state.last_update_ms != last_update_ms {
+                        last_update_ms = state.last_update_ms;
+                        updates += 1;
+                    }
+                    last_line = Some(render::status_line(market, state, status, ttl, expired, opts.verbose));
+                }
+            }
+            let out_of_time = deadline.is_some_and(|d| Instant::now() >= d);
+            let out_of_updates = opts.max_updates.is_some_and(|max| updates >= max);
+            if out_of_time || out_of_updates {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    )
+    .await;
 
-    println!("{}", m.title);
-    let end_ts = m.end_ts;
-
-    // Start orderbook data layer
-    let handle = orderbook::spawn(orderbook::OrderbookConfig {
-        token_up: m.token_up,
-        token_down: m.token_down,
-    });
-
-    // Subscribe til updates
-    let mut updates = handle.subscribe_updates();
-
-    loop {
-        match updates.recv().await {
-            Ok(_) => {
-                let state = handle.get_current_state().await;
-                let now = chrono::Utc::now().timestamp();
-                let ttl = end_ts - now;
-
-                print!("\rTTL:{:>4}s | UP {}/{} - {}/{} | DOWN {}/{} - {}/{}    ",
-                    ttl,
-                    state.up_bid_price, state.up_bid_size,
-                    state.up_ask_price, state.up_ask_size,
-                    state.down_bid_price, state.down_bid_size,
-                    state.down_ask_price, state.down_ask_size,
-                );
-                let _ = std::io::stdout().flush();
-
-                if ttl <= 0 {
-                    println!("\nMarked udløbet!");
-                    break;
+    note(format!("\nAfsluttet efter {} opdateringer", updates));
+    if let Some(line) = last_line {
+        note(format!("Sidste state: {}", line));
+    }
+}
+
+/// Kommandolinje-flag
+struct Options {
+    verbose: bool,
+    /// Én JSON-linje pr. opdatering på stdout i stedet for statuslinjen
+    json: bool,
+    /// Log-niveau eller filter-direktiv (fx `debug` eller `polymarket::orderbook=trace`)
+    log_level: String,
+    /// Sekunder bogen fortsat observeres efter `end_ts`
+    post_expiry_observe_secs: i64,
+    /// Optag top-of-book som JSONL til denne fil
+    record_path: Option<std::path::PathBuf>,
+    /// Revisionslog for arb-signaler
+    audit_path: Option<std::path::PathBuf>,
+    /// Gem/genoptag det aktuelle marked i denne fil
+    state_path: Option<std::path::PathBuf>,
+    /// Papirhandl arb-signaler med højst så mange par pr. handel
+    paper_max_pairs: Option<Decimal>,
+    /// Stop efter så lang tid
+    duration: Option<Duration>,
+    /// Stop efter så mange state-opdateringer
+    max_updates: Option<u64>,
+    /// Adresse for Prometheus `/metrics` (kræver feature `metrics`)
+    #[cfg(feature = "metrics")]
+    metrics_addr: Option<std::net::SocketAddr>,
+}
+
+impl Options {
+    fn from_args() -> Self {
+        let mut opts = Options {
+            verbose: false,
+            json: false,
+            log_level: "info".to_string(),
+            post_expiry_observe_secs: 0,
+            record_path: None,
+            audit_path: None,
+            state_path: None,
+            paper_max_pairs: None,
+            duration: None,
+            max_updates: None,
+            #[cfg(feature = "metrics")]
+            metrics_addr: None,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-v" | "--verbose" => opts.verbose = true,
+                "--json" => opts.json = true,
+                "--log-level" => {
+                    if let Some(level) = args.next() {
+                        opts.log_level = level;
+                    }
+                }
+                "--post-expiry-observe" => {
+                    opts.post_expiry_observe_secs =
+                        args.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+                }
+                "--record" => opts.record_path = args.next().map(Into::into),
+                "--audit" => opts.audit_path = args.next().map(Into::into),
+                "--state-file" => opts.state_path = args.next().map(Into::into),
+                "--paper" => opts.paper_max_pairs = args.next().and_then(|v| v.parse().ok()),
+                "--duration" => {
+                    opts.duration = args.next().and_then(|v| v.parse().ok()).map(Duration::from_secs);
                 }
+                "--max-updates" => opts.max_updates = args.next().and_then(|v| v.parse().ok()),
+                #[cfg(feature = "metrics")]
+                "--metrics" => opts.metrics_addr = args.next().and_then(|v| v.parse().ok()),
+                _ => {}
             }
-            Err(_) => break,
         }
+        opts
     }
 }
@@ -1,76 +1,1136 @@
-//! Market discovery - finder aktivt BTC Up/Down 15min marked.
+//! Market discovery - finder aktivt Up/Down marked (standard BTC 15min).
 
 use reqwest::Client;
-use serde::Deserialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
 
-const GAMMA_API: &str = "https://gamma-api.polymarket.com/events/slug/";
-const INTERVAL: i64 = 900;
+use crate::clock::{self, Clock};
+use crate::orderbook::{Labels, DEFAULT_TICK_SIZE};
 
-#[derive(Deserialize)]
+/// Gamma event-opslag - slug'en hæftes på
+pub const DEFAULT_GAMMA_API: &str = "https://gamma-api.polymarket.com/events/slug/";
+/// CLOB bog-snapshot - token id hæftes på
+pub const DEFAULT_CLOB_BOOK_API: &str = "https://clob.polymarket.com/book?token_id=";
+
+/// Standard-timeout pr. HTTP-request i discovery
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Hvor længe `resolve_outcome` venter på afregning
+pub const DEFAULT_RESOLUTION_TIMEOUT: Duration = Duration::from_secs(600);
+/// Pause mellem opslag mens et marked afventer afregning
+const RESOLUTION_POLL: Duration = Duration::from_secs(15);
+
+/// Standard-loft for samtidige Gamma-requests
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 4;
+
+static GAMMA_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+static GAMMA_IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Sæt loftet for samtidige Gamma-requests for hele processen.
+/// Skal kaldes før første request - returnerer false hvis loftet allerede er fastlagt.
+pub fn set_max_concurrent_requests(max: usize) -> bool {
+    GAMMA_PERMITS.set(Semaphore::new(max.max(1))).is_ok()
+}
+
+/// Antal Gamma-requests der er i gang lige nu
+pub fn requests_in_flight() -> usize {
+    GAMMA_IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+fn gamma_permits() -> &'static Semaphore {
+    GAMMA_PERMITS.get_or_init(|| Semaphore::new(DEFAULT_MAX_CONCURRENT_REQUESTS))
+}
+
+/// Tæller en request som i gang indtil den droppes
+struct InFlight;
+
+impl InFlight {
+    fn start() -> Self {
+        GAMMA_IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        GAMMA_IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Et marked i et Gamma event
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct MarketData {
+pub struct GammaMarket {
+    #[serde(default)]
+    pub condition_id: String,
+    #[serde(default)]
+    pub question: String,
+    #[serde(default)]
+    pub slug: String,
+    /// JSON-encodet array af outcome-navne, fx `["Up", "Down"]`
+    #[serde(default)]
+    pub outcomes: String,
+    /// JSON-encodet array af token ids i samme rækkefølge som `outcomes`
+    #[serde(default)]
+    pub clob_token_ids: String,
+    /// Mindste prisskridt - tal eller streng afhængigt af endpoint
     #[serde(default)]
-    clob_token_ids: String,
+    pub order_price_min_tick_size: Option<serde_json::Value>,
+    /// Tager imod ordrer - et aktivt market kan være sat på pause eller under afregning.
+    /// None når feltet mangler, hvilket tolkes som ja
+    #[serde(default, alias = "accepting_orders")]
+    pub accepting_orders: Option<bool>,
+    #[serde(default)]
+    pub closed: bool,
+    /// JSON-encodet array af priser i samme rækkefølge som `outcomes` - `["1", "0"]` efter afregning
+    #[serde(default)]
+    pub outcome_prices: String,
+    /// UMA-orakelets status, `resolved` når udfaldet er endeligt
+    #[serde(default)]
+    pub uma_resolution_status: String,
 }
 
-#[derive(Deserialize)]
+impl GammaMarket {
+    /// Token ids afkodet fra `clob_token_ids`
+    pub fn token_ids(&self) -> Option<Vec<String>> {
+        serde_json::from_str(&self.clob_token_ids).ok()
+    }
+
+    /// Markedets prisskridt, None hvis feltet mangler eller er ulæseligt
+    pub fn tick_size(&self) -> Option<Decimal> {
+        let tick = match self.order_price_min_tick_size.as_ref()? {
+            serde_json::Value::String(s) => s.parse().ok()?,
+            serde_json::Value::Number(n) => n.to_string().parse().ok()?,
+            _ => return None,
+        };
+        (tick > Decimal::ZERO).then_some(tick)
+    }
+
+    /// Outcome-navne afkodet fra `outcomes`
+    pub fn outcome_names(&self) -> Option<Vec<String>> {
+        serde_json::from_str(&self.outcomes).ok()
+    }
+
+    /// Token for det vindende outcome, når marketet er afregnet: UMA melder `resolved`
+    /// eller marketet er lukket, og præcis ét outcome står til 1
+    pub fn winning_token(&self) -> Option<String> {
+        let resolved = self.uma_resolution_status.eq_ignore_ascii_case("resolved") || self.closed;
+        if !resolved {
+            return None;
+        }
+        let prices: Vec<String> = serde_json::from_str(&self.outcome_prices).ok()?;
+        let prices: Vec<Decimal> = prices.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+        let mut winners = prices.iter().enumerate().filter(|(_, p)| **p == Decimal::ONE);
+        let (winner, _) = winners.next()?;
+        if winners.next().is_some() {
+            return None;
+        }
+        self.token_ids()?.into_iter().nth(winner)
+    }
+}
+
+/// Gamma event som returneret af `/events/slug/{slug}`
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Event {
+pub struct GammaEvent {
+    #[serde(default)]
+    pub slug: String,
+    #[serde(default)]
+    pub title: String,
     #[serde(default)]
-    title: String,
+    pub active: bool,
     #[serde(default)]
-    active: bool,
+    pub closed: bool,
     #[serde(default)]
-    closed: bool,
+    pub end_date: String,
+    /// Samme tidspunkt i en variant Gamma nogle gange sender i stedet for `endDate`
     #[serde(default)]
-    end_date: String,
+    pub end_date_iso: String,
     #[serde(default)]
-    markets: Vec<MarketData>,
+    pub markets: Vec<GammaMarket>,
+}
+
+/// Hvorfor discovery ikke fandt et marked
+#[derive(Debug)]
+pub enum DiscoveryError {
+    /// Netværks-/HTTP-fejl mod Gamma API
+    Http { slug: String, source: reqwest::Error },
+    /// Opslaget nåede ikke at svare inden for discovery-budgettet (`total_timeout`)
+    Timeout { slug: String, budget: Duration },
+    /// Svaret kunne ikke læses som et event
+    Deserialize { slug: String, source: serde_json::Error },
+    /// Alle slots blev prøvet uden et aktivt marked
+    NoActiveSlot { tried: Vec<String> },
+    /// Eventet findes, men har ingen markets - tyder på ændret event-struktur, ikke manglende slot
+    EmptyEvent { slug: String },
+    /// Marked fundet, men token-listen er ulæselig eller ufuldstændig
+    MalformedTokens { slug: String },
+    /// Eventet har flere markets, men intet matcher `DiscoveryParams::market`
+    NoMatchingMarket { slug: String, markets: usize },
+    /// Marketet er aktivt, men tager ikke imod ordrer - bogen ville stå stille
+    NotAcceptingOrders { slug: String },
+}
+
+impl std::fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiscoveryError::Http { slug, source } => write!(f, "HTTP-fejl for {}: {}", slug, source),
+            DiscoveryError::Timeout { slug, budget } => write!(f, "{} nåede ikke at svare inden for {:?}", slug, budget),
+            DiscoveryError::Deserialize { slug, source } => {
+                write!(f, "ulæseligt event for {}: {}", slug, source)
+            }
+            DiscoveryError::NoActiveSlot { tried } => {
+                write!(f, "intet aktivt slot blandt {}", tried.join(", "))
+            }
+            DiscoveryError::EmptyEvent { slug } => write!(f, "event {} fundet men ingen markets", slug),
+            DiscoveryError::MalformedTokens { slug } => write!(f, "ugyldige tokens for {}", slug),
+            DiscoveryError::NoMatchingMarket { slug, markets } => {
+                write!(f, "intet af {} markets i {} matcher", markets, slug)
+            }
+            DiscoveryError::NotAcceptingOrders { slug } => write!(f, "{} tager ikke imod ordrer", slug),
+        }
+    }
+}
+
+impl std::error::Error for DiscoveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DiscoveryError::Http { source, .. } => Some(source),
+            DiscoveryError::Deserialize { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Hent et event via slug - Ok(None) hvis slug'en ikke findes (404 o.l.),
+/// Err ved netværksfejl, timeout eller ulæseligt svar
+pub async fn fetch_event(client: &Client, slug: &str) -> Result<Option<GammaEvent>, DiscoveryError> {
+    fetch_event_within(client, DEFAULT_GAMMA_API, slug, DEFAULT_REQUEST_TIMEOUT).await
+}
+
+/// Som `fetch_event`, med eksplicit timeout (timeout giver `DiscoveryError::Http`)
+async fn fetch_event_within(
+    client: &Client,
+    gamma_api: &str,
+    slug: &str,
+    timeout: Duration,
+) -> Result<Option<GammaEvent>, DiscoveryError> {
+    let url = format!("{}{}", gamma_api, slug);
+
+    // Alle Gamma-kald går gennem samme loft, så parallel discovery ikke rate-limites
+    let _permit = gamma_permits().acquire().await.expect("Gamma-semaforen lukkes aldrig");
+    let _in_flight = InFlight::start();
+
+    let http_err = |source| DiscoveryError::Http {
+        slug: slug.to_string(),
+        source,
+    };
+    let resp = client.get(&url).timeout(timeout).send().await.map_err(http_err)?;
+    if !resp.status().is_success() {
+        return Ok(None);
+    }
+    let body = resp.text().await.map_err(http_err)?;
+    serde_json::from_str(&body)
+        .map(Some)
+        .map_err(|source| DiscoveryError::Deserialize {
+            slug: slug.to_string(),
+            source,
+        })
 }
 
+#[derive(Debug, Clone)]
 pub struct Market {
+    /// Gamma-slug for eventet
+    pub slug: String,
     pub title: String,
     pub end_ts: i64,
     pub token_up: String,
     pub token_down: String,
+    /// Outcome-navne for de to tokens (fx Up/Down eller Yes/No)
+    pub labels: Labels,
+    /// Alle outcomes i det valgte market med deres token, i Gamma-rækkefølge
+    pub outcomes: Vec<Outcome>,
+    /// Prisskridt (0.01 hvis Gamma ikke oplyser det)
+    pub tick_size: Decimal,
+}
+
+/// Et outcome og det token der handler det
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub name: String,
+    pub token_id: String,
+}
+
+/// Valg når flere slots er aktive samtidig (typisk kortvarigt omkring en grænse)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Første aktive slot i `slot_offsets`-rækkefølge - stopper søgningen ved første fund
+    FirstActive,
+    /// Senest udløbende slot - mest tid at handle i
+    #[default]
+    LatestEnd,
+    /// Mindste UP-spread ifølge et hurtigt kig i CLOB-bogen; lige spread afgøres af `end_ts`
+    TightestSpread,
+}
+
+/// Hvilket market i et event der følges, når eventet har flere (fx strike-bånd)
+#[derive(Clone, Default)]
+pub enum MarketSelector {
+    /// Det market hvis question eller slug ligner Up/Down; et event med ét market tages som det er
+    #[default]
+    UpDown,
+    /// Fast position i `event.markets`
+    Index(usize),
+    /// Første market prædikatet accepterer
+    Matching(Arc<dyn Fn(&GammaMarket) -> bool + Send + Sync>),
+}
+
+impl std::fmt::Debug for MarketSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarketSelector::UpDown => f.write_str("UpDown"),
+            MarketSelector::Index(i) => f.debug_tuple("Index").field(i).finish(),
+            MarketSelector::Matching(_) => f.write_str("Matching(..)"),
+        }
+    }
+}
+
+impl MarketSelector {
+    fn select<'a>(&self, markets: &'a [GammaMarket]) -> Option<&'a GammaMarket> {
+        match self {
+            MarketSelector::UpDown if markets.len() == 1 => markets.first(),
+            MarketSelector::UpDown => markets.iter().find(|m| looks_up_down(m)),
+            MarketSelector::Index(i) => markets.get(*i),
+            MarketSelector::Matching(predicate) => markets.iter().find(|m| predicate(m)),
+        }
+    }
+}
+
+/// Question eller slug i stil med "Bitcoin Up or Down" / `btc-updown-15m-...`
+fn looks_up_down(m: &GammaMarket) -> bool {
+    let question = m.question.to_ascii_lowercase();
+    let slug = m.slug.to_ascii_lowercase();
+    question.contains("up or down") || slug.contains("updown") || slug.contains("up-or-down")
+}
+
+/// Hvilken markedsserie der søges i, og hvordan
+#[derive(Debug, Clone)]
+pub struct DiscoveryParams {
+    /// Asset-symbol i slug'en, fx `btc`, `eth`, `sol`
+    pub asset: String,
+    /// Slot-længde i sekunder (900 = 15min, 3600 = 1h)
+    pub interval_secs: i64,
+    /// Slug-skabelon - `{asset}` og `{slot}` erstattes, fx `{asset}-updown-15m-{slot}`
+    pub slug_template: String,
+    /// Slots der prøves, relativt til det nuværende (-1 = forrige, 1 = næste), i rækkefølge
+    pub slot_offsets: Vec<i64>,
+    /// Valg hvis flere slots er aktive samtidig
+    pub tie_break: TieBreak,
+    /// Maks. tid pr. HTTP-request
+    pub request_timeout: Duration,
+    /// Maks. samlet tid for alle slot-opslag - resterende slots springes over
+    pub total_timeout: Duration,
+    /// Gamma event-endpoint (slug hæftes på) - kan peges mod en lokal mock
+    pub gamma_api: String,
+    /// CLOB bog-endpoint (token id hæftes på)
+    pub clob_book_api: String,
+    /// Kilde til "nu" for slot-beregning og udløb
+    pub clock: Arc<dyn Clock>,
+    /// Valg af market i events med flere
+    pub market: MarketSelector,
+}
+
+impl DiscoveryParams {
+    /// BTC Up/Down 15min - den oprindelige standard
+    pub fn btc_15m() -> Self {
+        Self::new("btc", 900, "{asset}-updown-15m-{slot}")
+    }
+
+    pub fn new(asset: impl Into<String>, interval_secs: i64, slug_template: impl Into<String>) -> Self {
+        Self {
+            asset: asset.into(),
+            interval_secs,
+            slug_template: slug_template.into(),
+            slot_offsets: vec![-1, 0, 1, 2, 3],
+            tie_break: TieBreak::default(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            total_timeout: Duration::from_secs(15),
+            gamma_api: DEFAULT_GAMMA_API.to_string(),
+            clob_book_api: DEFAULT_CLOB_BOOK_API.to_string(),
+            clock: clock::system(),
+            market: MarketSelector::default(),
+        }
+    }
+
+    /// Slug for slot'et der starter ved `slot` (unix sekunder)
+    pub fn slug(&self, slot: i64) -> String {
+        self.slug_template
+            .replace("{asset}", &self.asset)
+            .replace("{slot}", &slot.to_string())
+    }
+}
+
+impl Default for DiscoveryParams {
+    fn default() -> Self {
+        Self::btc_15m()
+    }
+}
+
+/// Find aktivt BTC 15min marked med standardindstillinger
+pub async fn find_active(client: &Client) -> Result<Market, DiscoveryError> {
+    find_active_with(client, &DiscoveryParams::default()).await
 }
 
-pub async fn find_active(client: &Client) -> Option<Market> {
-    let now = chrono::Utc::now().timestamp();
-    let base = now - (now % INTERVAL);
+/// Find aktivt marked for serien beskrevet af `params`.
+/// Alle slots slås op parallelt inden for `total_timeout`.
+///
+/// Fejl pr. slot registreres (også opslag afbrudt af budgettet); findes intet aktivt
+/// slot returneres den første fejl, eller `NoActiveSlot` hvis alle slots blot var
+/// fraværende/lukkede.
+pub async fn find_active_with(client: &Client, params: &DiscoveryParams) -> Result<Market, DiscoveryError> {
+    let interval = params.interval_secs.max(1);
+    let now = params.clock.now_secs();
+    let base = now - (now % interval);
+    let deadline = tokio::time::Instant::now() + params.total_timeout;
+
+    let slots: Vec<(i64, String)> = params
+        .slot_offsets
+        .iter()
+        .map(|offset| {
+            let slot = base + (offset * interval);
+            (slot, params.slug(slot))
+        })
+        .collect();
 
-    for offset in [0, 1, 2] {
-        let slot = base + (offset * INTERVAL);
-        let slug = format!("btc-updown-15m-{}", slot);
-        let url = format!("{}{}", GAMMA_API, slug);
+    // Alle opslag sendes samtidig (begrænset af Gamma-semaforen), og hvert
+    // får højst den tid der er tilbage af det samlede budget
+    let lookups = slots.iter().map(|(_, slug)| {
+        let fetch = fetch_event_within(client, &params.gamma_api, slug, params.request_timeout);
+        tokio::time::timeout_at(deadline, fetch)
+    });
+    let results = futures_util::future::join_all(lookups).await;
 
-        let Ok(resp) = client.get(&url).send().await else {
+    // Resultaterne gennemgås i `slot_offsets`-rækkefølge, så valget er deterministisk
+    let mut candidates = Vec::new();
+    let mut errors = Vec::new();
+    let mut tried = Vec::new();
+    for ((slot, slug), result) in slots.into_iter().zip(results) {
+        tried.push(slug.clone());
+        let Ok(result) = result else {
+            warn!(budget = ?params.total_timeout, %slug, "discovery-budget brugt - springer over");
+            errors.push(DiscoveryError::Timeout {
+                slug,
+                budget: params.total_timeout,
+            });
             continue;
         };
-        if !resp.status().is_success() {
+
+        let event = match result {
+            Ok(Some(event)) => event,
+            Ok(None) => {
+                debug!(%slug, "slot ikke fundet");
+                continue;
+            }
+            Err(e) => {
+                warn!(%slug, error = %e, "opslag fejlede");
+                errors.push(e);
+                continue;
+            }
+        };
+
+        // Eventet findes men har ingen markets - meldes i fejlen hvis intet slot er aktivt
+        if event.markets.is_empty() {
+            warn!(%slug, "event fundet men ingen markets");
+            errors.push(DiscoveryError::EmptyEvent { slug });
             continue;
         }
-        let Ok(event) = resp.json::<Event>().await else {
-            continue;
-        };
 
         if event.active && !event.closed {
-            if let Some(m) = event.markets.first() {
-                let tokens: Vec<String> = serde_json::from_str(&m.clob_token_ids).ok()?;
-                if tokens.len() >= 2 {
-                    let end_ts = chrono::DateTime::parse_from_rfc3339(&event.end_date)
-                        .map(|dt| dt.timestamp())
-                        .unwrap_or(0);
-
-                    return Some(Market {
-                        title: event.title,
-                        end_ts,
-                        token_up: tokens[0].clone(),
-                        token_down: tokens[1].clone(),
-                    });
+            match market_from_event(&slug, slot + interval, event, &params.market) {
+                // Et tidligere slot kan stadig stå som aktivt efter sin sluttid
+                Ok(market) if market.end_ts <= now => {
+                    debug!(%slug, "aktivt men udløbet");
+                }
+                Ok(market) => {
+                    candidates.push(market);
+                    if params.tie_break == TieBreak::FirstActive {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!(error = %e, "ubrugeligt marked");
+                    errors.push(e);
                 }
             }
         }
     }
+
+    if candidates.is_empty() {
+        return Err(match errors.into_iter().next() {
+            Some(e) => e,
+            None => DiscoveryError::NoActiveSlot { tried },
+        });
+    }
+
+    let chosen = match params.tie_break {
+        TieBreak::FirstActive => candidates.into_iter().next(),
+        TieBreak::LatestEnd => candidates.into_iter().max_by_key(|m| m.end_ts),
+        TieBreak::TightestSpread => {
+            if candidates.len() < 2 {
+                return Ok(candidates.remove(0));
+            }
+            let mut ranked = Vec::new();
+            for market in candidates {
+                let spread = peek_spread(client, &params.clob_book_api, &market.token_up, params.request_timeout).await;
+                ranked.push((spread, market));
+            }
+            // Manglende bog rangerer sidst; ellers mindst spread, så senest end_ts
+            ranked
+                .into_iter()
+                .min_by(|(sa, ma), (sb, mb)| {
+                    let sa = sa.unwrap_or(Decimal::MAX);
+                    let sb = sb.unwrap_or(Decimal::MAX);
+                    sa.cmp(&sb).then(mb.end_ts.cmp(&ma.end_ts))
+                })
+                .map(|(_, market)| market)
+        }
+    };
+    if let Some(m) = &chosen {
+        info!(slug = %m.slug, end_ts = m.end_ts, "marked fundet");
+    }
+    chosen.ok_or(DiscoveryError::NoActiveSlot { tried })
+}
+
+/// Det der gemmes om det aktuelle marked mellem genstarter
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedMarket {
+    slug: String,
+    token_up: String,
+    token_down: String,
+    end_ts: i64,
+}
+
+/// Gem markedets slug, tokens og sluttid i `path`, så det kan genoptages efter en genstart.
+/// Skrives via en midlertidig fil, så et nedbrud aldrig efterlader en halv fil.
+pub fn save_last(path: &Path, market: &Market) -> std::io::Result<()> {
+    let saved = SavedMarket {
+        slug: market.slug.clone(),
+        token_up: market.token_up.clone(),
+        token_down: market.token_down.clone(),
+        end_ts: market.end_ts,
+    };
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec(&saved)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Det vindende outcome for et udløbet marked med standardindstillinger og -timeout
+pub async fn resolve_outcome(client: &Client, market: &Market) -> Option<Outcome> {
+    resolve_outcome_with(client, &DiscoveryParams::default(), market, DEFAULT_RESOLUTION_TIMEOUT).await
+}
+
+/// Slå markedet op indtil Gamma melder et udfald, højst i `timeout`.
+/// None hvis det ikke er afregnet inden da - kalderen blokeres aldrig længere
+pub async fn resolve_outcome_with(
+    client: &Client,
+    params: &DiscoveryParams,
+    market: &Market,
+    timeout: Duration,
+) -> Option<Outcome> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let lookup = fetch_event_within(client, &params.gamma_api, &market.slug, params.request_timeout);
+        match tokio::time::timeout_at(deadline, lookup).await {
+            Err(_) => break,
+            Ok(Ok(Some(event))) => {
+                // Samme market som vi fulgte - genkendt på token, ikke position
+                let winner = event
+                    .markets
+                    .iter()
+                    .find(|m| m.token_ids().is_some_and(|ids| ids.contains(&market.token_up)))
+                    .and_then(GammaMarket::winning_token);
+                if let Some(token) = winner {
+                    let outcome = market.outcomes.iter().find(|o| o.token_id == token).cloned();
+                    info!(slug = %market.slug, winner = ?outcome.as_ref().map(|o| &o.name), "marked afregnet");
+                    return outcome;
+                }
+                debug!(slug = %market.slug, "endnu ikke afregnet");
+            }
+            Ok(Ok(None)) => debug!(slug = %market.slug, "event ikke fundet ved afregning"),
+            Ok(Err(e)) => debug!(slug = %market.slug, error = %e, "opslag ved afregning fejlede"),
+        }
+        if tokio::time::Instant::now() + RESOLUTION_POLL >= deadline {
+            break;
+        }
+        tokio::time::sleep(RESOLUTION_POLL).await;
+    }
+    warn!(slug = %market.slug, ?timeout, "intet udfald inden for tidsfristen");
     None
 }
+
+/// Genoptag markedet gemt af `save_last`, hvis det stadig er aktivt med samme tokens.
+/// None ved manglende, ulæselig eller forældet fil - kalderen falder tilbage til discovery.
+/// Gamma-endpoint, timeout og ur tages fra `params`.
+pub async fn resume(client: &Client, params: &DiscoveryParams, path: &Path) -> Option<Market> {
+    let raw = std::fs::read(path).ok()?;
+    let saved: SavedMarket = match serde_json::from_slice(&raw) {
+        Ok(saved) => saved,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "ignorerer ulæselig markedsfil");
+            return None;
+        }
+    };
+    if saved.end_ts <= params.clock.now_secs() {
+        return None;
+    }
+
+    let event = match fetch_event_within(client, &params.gamma_api, &saved.slug, params.request_timeout).await {
+        Ok(Some(event)) if event.active && !event.closed => event,
+        Ok(_) => return None,
+        Err(e) => {
+            warn!(slug = %saved.slug, error = %e, "kan ikke genoptage marked");
+            return None;
+        }
+    };
+    let market = market_from_event(&saved.slug, saved.end_ts, event, &params.market).ok()?;
+    let same_tokens = market.token_up == saved.token_up && market.token_down == saved.token_down;
+    if !same_tokens || market.end_ts <= params.clock.now_secs() {
+        return None;
+    }
+    info!(slug = %market.slug, "genoptager marked fra før genstart");
+    Some(market)
+}
+
+/// `slot_end` bruges som sluttid hvis eventets egen ikke kan læses
+fn market_from_event(
+    slug: &str,
+    slot_end: i64,
+    event: GammaEvent,
+    selector: &MarketSelector,
+) -> Result<Market, DiscoveryError> {
+    let malformed = || DiscoveryError::MalformedTokens {
+        slug: slug.to_string(),
+    };
+    let m = selector
+        .select(&event.markets)
+        .ok_or_else(|| DiscoveryError::NoMatchingMarket {
+            slug: slug.to_string(),
+            markets: event.markets.len(),
+        })?;
+    if m.accepting_orders == Some(false) {
+        return Err(DiscoveryError::NotAcceptingOrders { slug: slug.to_string() });
+    }
+    let (up, down, outcomes) = labelled_pair(m).or_else(|| positional_pair(m)).ok_or_else(malformed)?;
+
+    // To ens eller tomme tokens ville give en bog der handles mod sig selv
+    let (token_up, token_down) = (&outcomes[up].token_id, &outcomes[down].token_id);
+    if !valid_token_id(token_up) || !valid_token_id(token_down) || token_up == token_down {
+        return Err(malformed());
+    }
+    let tick_size = m.tick_size().unwrap_or(DEFAULT_TICK_SIZE);
+
+    // En ulæselig sluttid må ikke blive 0 - så ser et sundt marked udløbet ud
+    let end_ts = parse_end_date(&event.end_date)
+        .or_else(|| parse_end_date(&event.end_date_iso))
+        .unwrap_or_else(|| {
+            warn!(%slug, end_date = ?event.end_date, "ugyldig end_date - bruger slot-slut");
+            slot_end
+        });
+
+    Ok(Market {
+        slug: slug.to_string(),
+        title: event.title,
+        end_ts,
+        token_up: outcomes[up].token_id.clone(),
+        token_down: outcomes[down].token_id.clone(),
+        labels: Labels::new(&outcomes[up].name, &outcomes[down].name),
+        outcomes,
+        tick_size,
+    })
+}
+
+/// Unix-tid fra Gammas sluttid: RFC3339 (også med `Z`), eller uden zone som UTC.
+/// None for tom eller ulæselig streng.
+fn parse_end_date(raw: &str) -> Option<i64> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(raw) {
+        return Some(dt.timestamp());
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(raw.trim_end_matches('Z'), fmt).ok())
+        .map(|dt| dt.and_utc().timestamp())
+}
+
+/// Et CLOB token id er et stort decimaltal eller `0x` + hex
+fn valid_token_id(id: &str) -> bool {
+    match id.strip_prefix("0x") {
+        Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()),
+    }
+}
+
+/// Outcome-navne der tæller som henholdsvis Up- og Down-siden
+const UP_NAMES: [&str; 2] = ["up", "yes"];
+const DOWN_NAMES: [&str; 2] = ["down", "no"];
+
+/// Outcomes i et market parret med deres tokens. Navne mangler hvis
+/// `outcomes` ikke kan afkodes eller ikke passer i længde med tokens.
+fn outcomes_of(m: &GammaMarket) -> Option<Vec<Outcome>> {
+    let tokens = m.token_ids()?;
+    let names = m
+        .outcome_names()
+        .filter(|names| names.len() == tokens.len())
+        .unwrap_or_default();
+    Some(
+        tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, token_id)| Outcome {
+                name: names.get(i).cloned().unwrap_or_default(),
+                token_id,
+            })
+            .collect(),
+    )
+}
+
+/// Up/Down-par fundet via outcome-navne (index up, index down, outcomes)
+fn labelled_pair(m: &GammaMarket) -> Option<(usize, usize, Vec<Outcome>)> {
+    let outcomes = outcomes_of(m)?;
+    let find = |names: &[&str]| {
+        outcomes
+            .iter()
+            .position(|o| names.iter().any(|n| o.name.eq_ignore_ascii_case(n)))
+    };
+    let up = find(&UP_NAMES)?;
+    let down = find(&DOWN_NAMES)?;
+    Some((up, down, outcomes))
+}
+
+/// Fallback når navne mangler: første token er Up, andet Down
+fn positional_pair(m: &GammaMarket) -> Option<(usize, usize, Vec<Outcome>)> {
+    let mut outcomes = outcomes_of(m)?;
+    if outcomes.len() < 2 {
+        return None;
+    }
+    for (o, default) in outcomes.iter_mut().zip(["Up", "Down"]) {
+        if o.name.is_empty() {
+            o.name = default.to_string();
+        }
+    }
+    Some((0, 1, outcomes))
+}
+
+#[derive(Deserialize)]
+struct BookLevel {
+    price: String,
+    #[serde(default)]
+    size: String,
+}
+
+#[derive(Deserialize)]
+struct BookSnapshot {
+    #[serde(default)]
+    bids: Vec<BookLevel>,
+    #[serde(default)]
+    asks: Vec<BookLevel>,
+}
+
+/// Fuldt CLOB-snapshot for et token som rå JSON - samme form som WS `book`-beskeden
+pub(crate) async fn fetch_book(
+    client: &Client,
+    book_api: &str,
+    token_id: &str,
+    timeout: Duration,
+) -> Result<serde_json::Value, reqwest::Error> {
+    let url = format!("{}{}", book_api, token_id);
+    client.get(&url).timeout(timeout).send().await?.error_for_status()?.json().await
+}
+
+/// Hurtigt kig i CLOB-bogen for et token: spread (bedste ask - bedste bid)
+async fn peek_spread(client: &Client, book_api: &str, token_id: &str, timeout: Duration) -> Option<Decimal> {
+    let url = format!("{}{}", book_api, token_id);
+    let resp = client.get(&url).timeout(timeout).send().await.ok()?;
+    let book: BookSnapshot = resp.json().await.ok()?;
+
+    // Rækkefølgen er ikke garanteret, og size 0 er et fjernet niveau
+    let prices = |levels: &[BookLevel]| -> Vec<Decimal> {
+        levels
+            .iter()
+            .filter(|l| l.size.parse::<Decimal>().is_ok_and(|size| !size.is_zero()))
+            .filter_map(|l| l.price.parse().ok())
+            .collect()
+    };
+    let best_bid = prices(&book.bids).into_iter().max()?;
+    let best_ask = prices(&book.asks).into_iter().min()?;
+    Some(best_ask - best_bid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Lokal HTTP-server - `respond` får stien og giver status og body
+    async fn serve<F>(respond: F) -> String
+    where
+        F: Fn(&str) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+                    let (status, body) = respond(&path);
+                    let response = format!(
+                        "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    /// Starten på det nuværende 15min-slot (delelig med 900)
+    const BASE: i64 = 1_700_000_100;
+
+    fn params(url: &str) -> DiscoveryParams {
+        let mut params = DiscoveryParams::btc_15m();
+        params.gamma_api = format!("{}/events/slug/", url);
+        params.clob_book_api = format!("{}/book?token_id=", url);
+        params.clock = Arc::new(MockClock::new((BASE + 60) * 1000));
+        params
+    }
+
+    fn slug(offset: i64) -> String {
+        format!("btc-updown-15m-{}", BASE + offset * 900)
+    }
+
+    fn event(active: bool, closed: bool, tokens: &str) -> String {
+        serde_json::json!({
+            "title": "Bitcoin Up or Down",
+            "active": active,
+            "closed": closed,
+            "markets": [{
+                "question": "Bitcoin Up or Down",
+                "outcomes": "[\"Up\", \"Down\"]",
+                "clobTokenIds": tokens,
+            }],
+        })
+        .to_string()
+    }
+
+    /// Svar pr. slot-offset; øvrige slugs giver 404
+    fn slots(events: Vec<(i64, String)>) -> impl Fn(&str) -> (u16, String) + Send + Sync + 'static {
+        let events: Vec<(String, String)> = events.into_iter().map(|(o, e)| (slug(o), e)).collect();
+        move |path| match events.iter().find(|(slug, _)| path.ends_with(slug.as_str())) {
+            Some((_, body)) => (200, body.clone()),
+            None => (404, String::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn advances_past_closed_slot() {
+        let url = serve(slots(vec![
+            (0, event(true, true, r#"["1", "2"]"#)),
+            (1, event(true, false, r#"["3", "4"]"#)),
+        ]))
+        .await;
+        let mut params = params(&url);
+        params.tie_break = TieBreak::FirstActive;
+
+        let market = find_active_with(&Client::new(), &params).await.unwrap();
+        assert_eq!(market.slug, slug(1));
+        assert_eq!(market.end_ts, BASE + 2 * 900);
+        assert_eq!((market.token_up.as_str(), market.token_down.as_str()), ("3", "4"));
+    }
+
+    #[tokio::test]
+    async fn searches_only_the_configured_window() {
+        // Offset 4 ligger uden for standardvinduet [-1, 3]
+        let url = serve(slots(vec![(4, event(true, false, r#"["1", "2"]"#))])).await;
+        let params = params(&url);
+
+        match find_active_with(&Client::new(), &params).await {
+            Err(DiscoveryError::NoActiveSlot { tried }) => {
+                assert_eq!(tried, (-1..=3).map(slug).collect::<Vec<_>>());
+            }
+            other => panic!("forventede NoActiveSlot, fik {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn looks_back_for_a_still_active_slot() {
+        // Forrige slot har en sluttid efter nu og er stadig aktivt
+        let mut body: serde_json::Value = serde_json::from_str(&event(true, false, r#"["1", "2"]"#)).unwrap();
+        let end = chrono::DateTime::from_timestamp(BASE + 120, 0).unwrap();
+        body["endDate"] = end.to_rfc3339().into();
+        let url = serve(slots(vec![(-1, body.to_string())])).await;
+
+        let market = find_active_with(&Client::new(), &params(&url)).await.unwrap();
+        assert_eq!(market.slug, slug(-1));
+        assert_eq!(market.end_ts, BASE + 120);
+    }
+
+    #[tokio::test]
+    async fn lookups_cut_off_by_the_budget_are_reported_as_timeouts() {
+        // Serveren tager imod forbindelsen men svarer aldrig
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        let mut params = params(&url);
+        params.total_timeout = Duration::from_millis(100);
+
+        match find_active_with(&Client::new(), &params).await {
+            Err(DiscoveryError::Timeout { slug: s, budget }) => {
+                assert_eq!((s, budget), (slug(-1), Duration::from_millis(100)));
+            }
+            other => panic!("forventede Timeout, fik {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn event_without_markets_is_reported() {
+        let empty = serde_json::json!({ "active": true, "closed": false, "markets": [] }).to_string();
+        let url = serve(slots(vec![(0, empty)])).await;
+
+        match find_active_with(&Client::new(), &params(&url)).await {
+            Err(DiscoveryError::EmptyEvent { slug: s }) => assert_eq!(s, slug(0)),
+            other => panic!("forventede EmptyEvent, fik {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn event_without_markets_does_not_hide_an_active_slot() {
+        let empty = serde_json::json!({ "active": true, "closed": false, "markets": [] }).to_string();
+        let url = serve(slots(vec![(0, empty), (1, event(true, false, r#"["1", "2"]"#))])).await;
+
+        assert_eq!(find_active_with(&Client::new(), &params(&url)).await.unwrap().slug, slug(1));
+    }
+
+    #[tokio::test]
+    async fn latest_end_is_the_default_tie_break() {
+        // Det tidligste slot har det smalleste UP-spread (0.02 mod 0.10)
+        let events = slots(vec![
+            (0, event(true, false, r#"["1", "2"]"#)),
+            (1, event(true, false, r#"["3", "4"]"#)),
+        ]);
+        let book = |bid: &str, ask: &str| {
+            serde_json::json!({ "bids": [{ "price": bid, "size": "5" }], "asks": [{ "price": ask, "size": "5" }] })
+                .to_string()
+        };
+        let url = serve(move |path| match path {
+            "/book?token_id=1" => (200, book("0.49", "0.51")),
+            "/book?token_id=3" => (200, book("0.45", "0.55")),
+            _ => events(path),
+        })
+        .await;
+        let mut params = params(&url);
+        assert_eq!(params.tie_break, TieBreak::LatestEnd);
+        assert_eq!(find_active_with(&Client::new(), &params).await.unwrap().slug, slug(1));
+
+        params.tie_break = TieBreak::FirstActive;
+        assert_eq!(find_active_with(&Client::new(), &params).await.unwrap().slug, slug(0));
+
+        params.tie_break = TieBreak::TightestSpread;
+        assert_eq!(find_active_with(&Client::new(), &params).await.unwrap().slug, slug(0));
+    }
+
+    #[tokio::test]
+    async fn yes_no_outcomes_are_labelled_and_mapped_by_name() {
+        // No står først - Yes skal stadig blive UP-benet
+        let mut body: serde_json::Value = serde_json::from_str(&event(true, false, r#"["1", "2"]"#)).unwrap();
+        body["markets"][0]["outcomes"] = r#"["No", "Yes"]"#.into();
+        let url = serve(slots(vec![(0, body.to_string())])).await;
+
+        let market = find_active_with(&Client::new(), &params(&url)).await.unwrap();
+        assert_eq!(market.labels, Labels::new("Yes", "No"));
+        assert_eq!((market.token_up.as_str(), market.token_down.as_str()), ("2", "1"));
+    }
+
+    #[tokio::test]
+    async fn gamma_requests_are_capped_in_flight() {
+        // Serveren holder hvert svar lidt og tæller hvor mange den har på én gang
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events/slug/", listener.local_addr().unwrap());
+        let (open, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (server_open, server_peak) = (open.clone(), peak.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (open, peak) = (server_open.clone(), server_peak.clone());
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let _ = socket.read(&mut buf).await;
+                    peak.fetch_max(open.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    let response = "HTTP/1.1 404 X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        let client = Client::new();
+        let requests: Vec<_> = (0..3 * DEFAULT_MAX_CONCURRENT_REQUESTS)
+            .map(|i| {
+                let (client, url) = (client.clone(), url.clone());
+                tokio::spawn(async move {
+                    fetch_event_within(&client, &url, &format!("slot-{}", i), DEFAULT_REQUEST_TIMEOUT).await
+                })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(requests_in_flight() <= DEFAULT_MAX_CONCURRENT_REQUESTS);
+        for request in requests {
+            assert!(matches!(request.await.unwrap(), Ok(None)));
+        }
+
+        // Andre tests deler semaforen, så loftet kan være nået med færre af vores egne
+        let peak = peak.load(Ordering::SeqCst);
+        assert!((1..=DEFAULT_MAX_CONCURRENT_REQUESTS).contains(&peak), "{}", peak);
+    }
+
+    #[test]
+    fn end_date_formats() {
+        assert_eq!(parse_end_date(""), None);
+        assert_eq!(parse_end_date("   "), None);
+        assert_eq!(parse_end_date("i morgen"), None);
+        assert_eq!(parse_end_date("2023-11-14T22:15"), None);
+
+        let ts = 1_700_000_100;
+        assert_eq!(parse_end_date("2023-11-14T22:15:00+00:00"), Some(ts));
+        assert_eq!(parse_end_date("2023-11-14T23:15:00+01:00"), Some(ts));
+        assert_eq!(parse_end_date("2023-11-14T22:15:00Z"), Some(ts));
+        assert_eq!(parse_end_date("2023-11-14T22:15:00.000Z"), Some(ts));
+        assert_eq!(parse_end_date("2023-11-14T22:15:00"), Some(ts));
+        assert_eq!(parse_end_date("2023-11-14 22:15:00"), Some(ts));
+    }
+
+    #[tokio::test]
+    async fn unreadable_end_date_falls_back_to_iso_then_the_slot_end() {
+        let mut iso: serde_json::Value = serde_json::from_str(&event(true, false, r#"["1", "2"]"#)).unwrap();
+        iso["endDate"] = "".into();
+        iso["endDateIso"] = "2023-11-14T22:20:00Z".into();
+        let mut bad: serde_json::Value = serde_json::from_str(&event(true, false, r#"["3", "4"]"#)).unwrap();
+        bad["endDate"] = "snart".into();
+        let url = serve(slots(vec![(0, iso.to_string()), (1, bad.to_string())])).await;
+        let mut first_active = params(&url);
+        first_active.tie_break = TieBreak::FirstActive;
+
+        let market = find_active_with(&Client::new(), &first_active).await.unwrap();
+        assert_eq!((market.slug, market.end_ts), (slug(0), 1_700_000_400));
+
+        // Kun slot 1 tilbage - dens sluttid kan ikke læses, så slot-slut bruges frem for 0
+        let url = serve(slots(vec![(1, bad.to_string())])).await;
+        let market = find_active_with(&Client::new(), &params(&url)).await.unwrap();
+        assert_eq!((market.slug, market.end_ts), (slug(1), BASE + 2 * 900));
+    }
+
+    #[tokio::test]
+    async fn peek_spread_skips_zero_size_levels_in_any_order() {
+        let book = serde_json::json!({
+            "bids": [
+                { "price": "0.30", "size": "5" },
+                { "price": "0.48", "size": "0" },
+                { "price": "0.45", "size": "7" },
+            ],
+            "asks": [
+                { "price": "0.60", "size": "1" },
+                { "price": "0.49", "size": "0" },
+                { "price": "0.52", "size": "2" },
+            ],
+        })
+        .to_string();
+        let url = serve(move |_| (200, book.clone())).await;
+        let book_api = format!("{}/book?token_id=", url);
+
+        let spread = peek_spread(&Client::new(), &book_api, "1", DEFAULT_REQUEST_TIMEOUT).await;
+        assert_eq!(spread, Some("0.07".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn peek_spread_needs_a_live_level_on_both_sides() {
+        let book = serde_json::json!({
+            "bids": [{ "price": "0.45", "size": "7" }],
+            "asks": [{ "price": "0.50", "size": "0" }],
+        })
+        .to_string();
+        let url = serve(move |_| (200, book.clone())).await;
+        let book_api = format!("{}/book?token_id=", url);
+
+        assert_eq!(peek_spread(&Client::new(), &book_api, "1", DEFAULT_REQUEST_TIMEOUT).await, None);
+    }
+
+    #[tokio::test]
+    async fn tick_size_is_read_from_the_market_with_a_cent_fallback() {
+        let mut fine: serde_json::Value = serde_json::from_str(&event(true, false, r#"["1", "2"]"#)).unwrap();
+        fine["markets"][0]["orderPriceMinTickSize"] = 0.001.into();
+        let mut text: serde_json::Value = serde_json::from_str(&event(true, false, r#"["3", "4"]"#)).unwrap();
+        text["markets"][0]["orderPriceMinTickSize"] = "0.01".into();
+        let url = serve(slots(vec![(0, fine.to_string()), (1, text.to_string()), (2, event(true, false, r#"["5", "6"]"#))])).await;
+        let mut params = params(&url);
+
+        // Tal, streng og manglende felt - ét slot ad gangen
+        for (tick, offset) in [("0.001", 0), ("0.01", 1), ("0.01", 2)] {
+            params.slot_offsets = vec![offset];
+            let market = find_active_with(&Client::new(), &params).await.unwrap();
+            assert_eq!((market.slug, market.tick_size), (slug(offset), tick.parse().unwrap()));
+        }
+    }
+
+    #[test]
+    fn duplicate_empty_or_malformed_tokens_are_rejected() {
+        let parse = |tokens: &str| -> GammaEvent { serde_json::from_str(&event(true, false, tokens)).unwrap() };
+        let from = |tokens: &str| market_from_event("slot", BASE, parse(tokens), &MarketSelector::default());
+
+        for tokens in [r#"["7", "7"]"#, r#"["", "8"]"#, r#"["7", ""]"#, r#"["7", "abc"]"#, r#"["0x", "8"]"#, r#"["7"]"#] {
+            match from(tokens) {
+                Err(DiscoveryError::MalformedTokens { slug }) => assert_eq!(slug, "slot"),
+                other => panic!("{}: forventede MalformedTokens, fik {:?}", tokens, other.map(|m| m.token_up)),
+            }
+        }
+        let market = from(r#"["123", "0xAbC"]"#).unwrap();
+        assert_eq!((market.token_up.as_str(), market.token_down.as_str()), ("123", "0xAbC"));
+    }
+
+    #[tokio::test]
+    async fn skips_a_slot_that_is_active_but_not_accepting_orders() {
+        let mut paused: serde_json::Value = serde_json::from_str(&event(true, false, r#"["1", "2"]"#)).unwrap();
+        // Feltet kommer i begge skrivemåder
+        paused["markets"][0]["accepting_orders"] = false.into();
+        let mut live: serde_json::Value = serde_json::from_str(&event(true, false, r#"["3", "4"]"#)).unwrap();
+        live["markets"][0]["acceptingOrders"] = true.into();
+        let url = serve(slots(vec![(0, paused.to_string()), (1, live.to_string())])).await;
+        let mut params = params(&url);
+        params.tie_break = TieBreak::FirstActive;
+
+        let market = find_active_with(&Client::new(), &params).await.unwrap();
+        assert_eq!((market.slug, market.token_up), (slug(1), "3".to_string()));
+    }
+}
@@ -0,0 +1,148 @@
+//! Prometheus-metrics for orderbook-laget (feature `metrics`).
+//!
+//! Tællere og gauges holdes i ét proces-globalt register og serveres som
+//! Prometheus tekstformat på `/metrics` af en minimal HTTP-server.
+
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::clock::Clock;
+use crate::orderbook::OrderbookState;
+
+/// Gauges for ét marked (nøglet på UP-token)
+#[derive(Default)]
+struct MarketGauges {
+    up_spread: Option<Decimal>,
+    down_spread: Option<Decimal>,
+    combined_cost: Option<Decimal>,
+    last_update_ms: i64,
+    arbs_detected: u64,
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Beskeder modtaget pr. token
+    messages: BTreeMap<String, u64>,
+    reconnects: u64,
+    resyncs: u64,
+    parse_failures: u64,
+    markets: BTreeMap<String, MarketGauges>,
+}
+
+type GaugeGetter = fn(&MarketGauges) -> Option<Decimal>;
+
+static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+fn with<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    let registry = REGISTRY.get_or_init(|| Mutex::new(Registry::default()));
+    f(&mut registry.lock().unwrap())
+}
+
+pub(crate) fn record_message(token: &str) {
+    with(|r| *r.messages.entry(token.to_string()).or_default() += 1);
+}
+
+pub(crate) fn record_reconnect() {
+    with(|r| r.reconnects += 1);
+}
+
+pub(crate) fn record_resync() {
+    with(|r| r.resyncs += 1);
+}
+
+pub(crate) fn record_parse_failure() {
+    with(|r| r.parse_failures += 1);
+}
+
+pub(crate) fn record_arb(market: &str) {
+    with(|r| r.markets.entry(market.to_string()).or_default().arbs_detected += 1);
+}
+
+/// Opdatér markedets gauges - kaldes hvor `StateUpdated` udsendes
+pub(crate) fn observe_state(market: &str, state: &OrderbookState) {
+    with(|r| {
+        let g = r.markets.entry(market.to_string()).or_default();
+        g.up_spread = state.up_spread();
+        g.down_spread = state.down_spread();
+        g.combined_cost = state.combined_cost();
+        g.last_update_ms = state.last_update_ms;
+    });
+}
+
+/// Alle metrics i Prometheus tekstformat - aldre regnes mod `clock`
+pub fn render(clock: &dyn Clock) -> String {
+    let now_ms = clock.now_ms();
+    let mut out = String::new();
+    with(|r| {
+        out.push_str("# TYPE orderbook_messages_total counter\n");
+        for (token, n) in &r.messages {
+            let _ = writeln!(out, "orderbook_messages_total{{token=\"{}\"}} {}", token, n);
+        }
+        out.push_str("# TYPE orderbook_reconnects_total counter\n");
+        let _ = writeln!(out, "orderbook_reconnects_total {}", r.reconnects);
+        out.push_str("# TYPE orderbook_resyncs_total counter\n");
+        let _ = writeln!(out, "orderbook_resyncs_total {}", r.resyncs);
+        out.push_str("# TYPE orderbook_parse_failures_total counter\n");
+        let _ = writeln!(out, "orderbook_parse_failures_total {}", r.parse_failures);
+
+        out.push_str("# TYPE orderbook_arbs_detected_total counter\n");
+        for (market, g) in &r.markets {
+            let _ = writeln!(out, "orderbook_arbs_detected_total{{market=\"{}\"}} {}", market, g.arbs_detected);
+        }
+
+        let gauges: [(&str, GaugeGetter); 3] = [
+            ("orderbook_up_spread", |g| g.up_spread),
+            ("orderbook_down_spread", |g| g.down_spread),
+            ("orderbook_combined_cost", |g| g.combined_cost),
+        ];
+        for (name, get) in gauges {
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            for (market, g) in &r.markets {
+                if let Some(v) = get(g) {
+                    let _ = writeln!(out, "{}{{market=\"{}\"}} {}", name, market, v);
+                }
+            }
+        }
+
+        out.push_str("# TYPE orderbook_last_update_age_seconds gauge\n");
+        for (market, g) in &r.markets {
+            if g.last_update_ms > 0 {
+                let age = (now_ms - g.last_update_ms) as f64 / 1000.0;
+                let _ = writeln!(out, "orderbook_last_update_age_seconds{{market=\"{}\"}} {}", market, age);
+            }
+        }
+    });
+    out
+}
+
+/// Servér `/metrics` på `addr` indtil tasken droppes
+pub async fn serve(addr: SocketAddr, clock: Arc<dyn Clock>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let clock = clock.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let Ok(n) = socket.read(&mut buf).await else { return };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request.split_whitespace().nth(1).unwrap_or("");
+
+            let response = if path == "/metrics" {
+                let body = render(clock.as_ref());
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
@@ -3,251 +3,3430 @@
 //! Ansvar: Modtag live orderbogsdata fra Polymarket WebSocket,
 //! vedligehold rolling state, og signal ved ændringer.
 
-use futures_util::{SinkExt, StreamExt};
-use std::sync::Arc;
-use tokio::sync::{broadcast, RwLock};
+use arc_swap::ArcSwap;
+use futures_util::{SinkExt, Stream, StreamExt};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, info, info_span, trace, warn, Instrument};
 
-const WS_URL: &str = "wss://ws-subscriptions-clob.polymarket.com/ws/market";
+use crate::arb::{self, ArbKind, ArbOpportunity, BreakerTrip, CircuitBreaker};
+use crate::audit::AuditLog;
+use crate::book::LevelBook;
+use crate::clock::{self, Clock};
+use crate::error::Error;
+use crate::market;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::recorder;
+pub use crate::book::Level;
+use crate::sizing;
+use crate::stats::{FeedStats, LatencyWindow};
+use crate::user::{self, ApiCredentials, UserState};
 
-/// Top-of-book state for et marked
-#[derive(Debug, Clone, Default)]
+/// Polymarkets market-kanal
+pub const DEFAULT_WS_URL: &str = "wss://ws-subscriptions-clob.polymarket.com/ws/market";
+
+/// Top-of-book state for et marked - None indtil siden har data.
+/// Serialiseres med priser som strenge, så de ikke mister præcision
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct OrderbookState {
-    pub up_bid_price: String,
-    pub up_bid_size: String,
-    pub up_ask_price: String,
-    pub up_ask_size: String,
-    pub down_bid_price: String,
-    pub down_bid_size: String,
-    pub down_ask_price: String,
-    pub down_ask_size: String,
+    pub up_bid_price: Option<Decimal>,
+    pub up_bid_size: Option<Decimal>,
+    pub up_ask_price: Option<Decimal>,
+    pub up_ask_size: Option<Decimal>,
+    pub down_bid_price: Option<Decimal>,
+    pub down_bid_size: Option<Decimal>,
+    pub down_ask_price: Option<Decimal>,
+    pub down_ask_size: Option<Decimal>,
+    /// Seneste handel på UP - kun med `OrderbookConfig::track_trades`
+    pub up_last_trade_price: Option<Decimal>,
+    pub up_last_trade_size: Option<Decimal>,
+    /// Seneste handel på DOWN
+    pub down_last_trade_price: Option<Decimal>,
+    pub down_last_trade_size: Option<Decimal>,
     pub last_update_ms: i64,
+    /// Seneste gang UP-bogen havde mindst ét niveau (0 = aldrig)
+    pub up_last_nonempty_ms: i64,
+    /// Seneste gang DOWN-bogen havde mindst ét niveau
+    pub down_last_nonempty_ms: i64,
+    /// Seneste besked for UP-tokenet, også en der ikke ændrede noget (0 = aldrig)
+    pub up_last_message_ms: i64,
+    /// Seneste besked for DOWN-tokenet
+    pub down_last_message_ms: i64,
+    /// Hvilke priser der er afledt af det modsatte bens komplement
+    pub derived: Derived,
+    /// Et afledt komplement ville krydse det modsatte bens egen bog (fx UP bid + DOWN bid > 1)
+    /// og er derfor ikke gemt - bøgerne er krydsede, ikke blot afledt forkert
+    pub complement_crossed: bool,
+    /// UP-spread overstiger `max_spread` - vises, men er ikke til at handle på
+    pub up_wide_spread: bool,
+    /// DOWN-spread overstiger `max_spread`
+    pub down_wide_spread: bool,
+    /// UP-benets datakvalitet
+    pub up_health: LegHealth,
+    /// DOWN-benets datakvalitet
+    pub down_health: LegHealth,
+}
+
+/// Markerer top-of-book felter der er beregnet som `1 - p` fra det modsatte ben
+/// i stedet for at komme fra benets egen bog
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Derived {
+    pub up_bid: bool,
+    pub up_ask: bool,
+    pub down_bid: bool,
+    pub down_ask: bool,
+}
+
+/// Hvor en pris kommer fra
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    /// Benets egen bog
+    Book,
+    /// `1 - p` fra det modsatte ben
+    Derived,
+}
+
+/// Implied sandsynlighed for et ben og kilden til prisen bag den
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImpliedProb {
+    pub prob: Decimal,
+    pub source: PriceSource,
+}
+
+/// Datakvalitet for et ben - et ben med gentagne dårlige beskeder slås fra
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum LegHealth {
+    #[default]
+    Healthy,
+    /// Sidste beskeder var dårlige, men tærsklen er ikke nået endnu
+    Suspect { consecutive_bad: u32 },
+    /// Benet ignoreres indtil `until_ms`, hvorefter det prøves igen
+    Disabled { until_ms: i64 },
+}
+
+impl OrderbookState {
+    /// UP mid ((bid + ask) / 2), None hvis en side mangler
+    pub fn up_mid(&self) -> Option<Decimal> {
+        mid(self.up_bid_price, self.up_ask_price)
+    }
+
+    /// DOWN mid ((bid + ask) / 2), None hvis en side mangler
+    pub fn down_mid(&self) -> Option<Decimal> {
+        mid(self.down_bid_price, self.down_ask_price)
+    }
+
+    /// UP microprice `(bid*ask_size + ask*bid_size) / (bid_size + ask_size)`
+    pub fn up_microprice(&self) -> Option<Decimal> {
+        microprice(
+            self.up_bid_price?,
+            self.up_bid_size?,
+            self.up_ask_price?,
+            self.up_ask_size?,
+        )
+    }
+
+    /// DOWN microprice - se `up_microprice`
+    pub fn down_microprice(&self) -> Option<Decimal> {
+        microprice(
+            self.down_bid_price?,
+            self.down_bid_size?,
+            self.down_ask_price?,
+            self.down_ask_size?,
+        )
+    }
+
+    /// Blandet fair-value estimat for P(UP) ud fra begge ben:
+    /// `(up_mid + (1 - down_mid)) / 2`.
+    ///
+    /// UP-benet siger `up_mid`, DOWN-benet siger `1 - down_mid`; gennemsnittet er mere
+    /// robust end hvert ben alene. None hvis et af benene mangler.
+    pub fn fair_up_prob(&self) -> Option<Decimal> {
+        Some((self.up_mid()? + (Decimal::ONE - self.down_mid()?)) / Decimal::TWO)
+    }
+
+    /// UP spread (ask - bid), None hvis en side mangler
+    pub fn up_spread(&self) -> Option<Decimal> {
+        Some(self.up_ask_price? - self.up_bid_price?)
+    }
+
+    /// DOWN spread (ask - bid), None hvis en side mangler
+    pub fn down_spread(&self) -> Option<Decimal> {
+        Some(self.down_ask_price? - self.down_bid_price?)
+    }
+
+    /// Pris for ét par (UP ask + DOWN ask) - under 1 er et købs-arb. None hvis en ask mangler
+    pub fn combined_cost(&self) -> Option<Decimal> {
+        Some(self.up_ask_price? + self.down_ask_price?)
+    }
+
+    /// Markedets sandsynlighed for UP = prisen for at købe UP (UP ask).
+    /// Benets egen ask bruges hvis den findes, ellers komplementet af DOWN bid
+    pub fn implied_prob_up(&self) -> Option<ImpliedProb> {
+        self.implied_prob(Side::Up)
+    }
+
+    /// Markedets sandsynlighed for DOWN = DOWN ask, med samme fallback
+    pub fn implied_prob_down(&self) -> Option<ImpliedProb> {
+        self.implied_prob(Side::Down)
+    }
+
+    fn implied_prob(&self, side: Side) -> Option<ImpliedProb> {
+        if let Some(prob) = self.real_ask(side) {
+            return Some(ImpliedProb { prob, source: PriceSource::Book });
+        }
+        let derived = match side {
+            Side::Up => self.up_ask_price,
+            Side::Down => self.down_ask_price,
+        };
+        derived.map(|prob| ImpliedProb { prob, source: PriceSource::Derived })
+    }
+
+    /// `1 - (up_ask + down_ask)` fra de rigtige bøger - positiv betyder købs-arb.
+    /// None hvis en ask mangler eller kun er afledt, da et komplement ikke kan handles
+    pub fn no_arb_residual(&self) -> Option<Decimal> {
+        Some(Decimal::ONE - (self.real_ask(Side::Up)? + self.real_ask(Side::Down)?))
+    }
+
+    /// Millisekunder fra seneste state-ændring til `now_ms`, None hvis der endnu ikke er data
+    pub fn age_ms(&self, now_ms: i64) -> Option<i64> {
+        if self.last_update_ms == 0 {
+            return None;
+        }
+        Some(now_ms - self.last_update_ms)
+    }
+
+    /// Begge ben er aktive og har en spread inden for `max_spread` - kun da er arb-signaler troværdige
+    pub fn is_tradeable(&self) -> bool {
+        !self.up_wide_spread
+            && !self.down_wide_spread
+            && !matches!(self.up_health, LegHealth::Disabled { .. })
+            && !matches!(self.down_health, LegHealth::Disabled { .. })
+    }
+
+    fn set_bid(&mut self, side: Side, price: Decimal, size: Decimal, derived: bool) {
+        match side {
+            Side::Up => {
+                self.up_bid_price = Some(price);
+                self.up_bid_size = Some(size);
+                self.derived.up_bid = derived;
+            }
+            Side::Down => {
+                self.down_bid_price = Some(price);
+                self.down_bid_size = Some(size);
+                self.derived.down_bid = derived;
+            }
+        }
+    }
+
+    fn set_ask(&mut self, side: Side, price: Decimal, size: Decimal, derived: bool) {
+        match side {
+            Side::Up => {
+                self.up_ask_price = Some(price);
+                self.up_ask_size = Some(size);
+                self.derived.up_ask = derived;
+            }
+            Side::Down => {
+                self.down_ask_price = Some(price);
+                self.down_ask_size = Some(size);
+                self.derived.down_ask = derived;
+            }
+        }
+    }
+
+    fn clear_bid(&mut self, side: Side) {
+        match side {
+            Side::Up => {
+                self.up_bid_price = None;
+                self.up_bid_size = None;
+                self.derived.up_bid = false;
+            }
+            Side::Down => {
+                self.down_bid_price = None;
+                self.down_bid_size = None;
+                self.derived.down_bid = false;
+            }
+        }
+    }
+
+    fn clear_ask(&mut self, side: Side) {
+        match side {
+            Side::Up => {
+                self.up_ask_price = None;
+                self.up_ask_size = None;
+                self.derived.up_ask = false;
+            }
+            Side::Down => {
+                self.down_ask_price = None;
+                self.down_ask_size = None;
+                self.derived.down_ask = false;
+            }
+        }
+    }
+
+    fn set_last_trade(&mut self, side: Side, price: Decimal, size: Decimal) {
+        match side {
+            Side::Up => {
+                self.up_last_trade_price = Some(price);
+                self.up_last_trade_size = Some(size);
+            }
+            Side::Down => {
+                self.down_last_trade_price = Some(price);
+                self.down_last_trade_size = Some(size);
+            }
+        }
+    }
+
+    /// Millisekunder siden seneste besked på det ben der har været stille længst.
+    /// Et ben der aldrig har sendt noget tæller fra 0
+    pub fn message_age_ms(&self, now_ms: i64) -> i64 {
+        now_ms - self.up_last_message_ms.min(self.down_last_message_ms)
+    }
+
+    /// Millisekunder siden benets bog sidst havde niveauer, None hvis den aldrig har
+    pub fn empty_for_ms(&self, side: Side, now_ms: i64) -> Option<i64> {
+        let last = match side {
+            Side::Up => self.up_last_nonempty_ms,
+            Side::Down => self.down_last_nonempty_ms,
+        };
+        (last > 0).then(|| now_ms - last)
+    }
+
+    /// Benets bid fra egen bog - None hvis den mangler eller er afledt
+    fn real_bid(&self, side: Side) -> Option<Decimal> {
+        match side {
+            Side::Up => self.up_bid_price.filter(|_| !self.derived.up_bid),
+            Side::Down => self.down_bid_price.filter(|_| !self.derived.down_bid),
+        }
+    }
+
+    /// Benets ask fra egen bog - None hvis den mangler eller er afledt
+    fn real_ask(&self, side: Side) -> Option<Decimal> {
+        match side {
+            Side::Up => self.up_ask_price.filter(|_| !self.derived.up_ask),
+            Side::Down => self.down_ask_price.filter(|_| !self.derived.down_ask),
+        }
+    }
+
+    fn health_mut(&mut self, side: Side) -> &mut LegHealth {
+        match side {
+            Side::Up => &mut self.up_health,
+            Side::Down => &mut self.down_health,
+        }
+    }
+}
+
+fn mid(bid: Option<Decimal>, ask: Option<Decimal>) -> Option<Decimal> {
+    Some((bid? + ask?) / Decimal::TWO)
+}
+
+/// Størrelsesvægtet mid - trækker mod den side med mindst volumen.
+/// None hvis den samlede size er nul.
+fn microprice(bid: Decimal, bid_size: Decimal, ask: Decimal, ask_size: Decimal) -> Option<Decimal> {
+    let total = bid_size + ask_size;
+    if total <= Decimal::ZERO {
+        return None;
+    }
+    Some((bid * ask_size + ask * bid_size) / total)
+}
+
+/// Et ben med spread over tærsklen betragtes som utroværdigt
+fn is_wide(spread: Option<Decimal>, max_spread: Option<Decimal>) -> bool {
+    match (spread, max_spread) {
+        (Some(s), Some(max)) => s > max,
+        _ => false,
+    }
+}
+
+/// Signal der udsendes ved state-ændring - hvilke top-of-book felter der flyttede sig.
+///
+/// Sendes kun når top-of-book faktisk ændrede sig; en besked der kun rører
+/// dybere niveauer giver intet signal. Ved coalescing er signalet foreningen
+/// af alle ændringer siden sidste udsendelse.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateUpdated {
+    pub up: LegChange,
+    pub down: LegChange,
+}
+
+/// Ændring på ét ben
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LegChange {
+    pub bid: bool,
+    pub ask: bool,
+    /// Mindst én af de ændrede priser er afledt af det modsatte bens komplement
+    pub derived: bool,
+}
+
+impl LegChange {
+    pub fn is_empty(&self) -> bool {
+        !self.bid && !self.ask
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            bid: self.bid || other.bid,
+            ask: self.ask || other.ask,
+            derived: self.derived || other.derived,
+        }
+    }
+}
+
+impl StateUpdated {
+    /// Ændringen på et ben
+    pub fn leg(&self, side: Side) -> LegChange {
+        match side {
+            Side::Up => self.up,
+            Side::Down => self.down,
+        }
+    }
+
+    /// Ingen top-of-book felter ændrede sig
+    pub fn is_empty(&self) -> bool {
+        self.up.is_empty() && self.down.is_empty()
+    }
+
+    /// Sammenlign top-of-book før og efter en opdatering
+    /// Et felt tæller som ændret hvis pris, size eller afledt-markering er ny
+    fn between(before: &OrderbookState, after: &OrderbookState) -> Self {
+        let (b, a) = (before, after);
+        let d = after.derived;
+        let up_bid = (b.up_bid_price, b.up_bid_size, b.derived.up_bid) != (a.up_bid_price, a.up_bid_size, d.up_bid);
+        let up_ask = (b.up_ask_price, b.up_ask_size, b.derived.up_ask) != (a.up_ask_price, a.up_ask_size, d.up_ask);
+        let down_bid =
+            (b.down_bid_price, b.down_bid_size, b.derived.down_bid) != (a.down_bid_price, a.down_bid_size, d.down_bid);
+        let down_ask =
+            (b.down_ask_price, b.down_ask_size, b.derived.down_ask) != (a.down_ask_price, a.down_ask_size, d.down_ask);
+        Self {
+            up: LegChange {
+                bid: up_bid,
+                ask: up_ask,
+                derived: (up_bid && d.up_bid) || (up_ask && d.up_ask),
+            },
+            down: LegChange {
+                bid: down_bid,
+                ask: down_ask,
+                derived: (down_bid && d.down_bid) || (down_ask && d.down_ask),
+            },
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            up: self.up.merge(other.up),
+            down: self.down.merge(other.down),
+        }
+    }
+}
+
+/// Ben i et binært marked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Up,
+    Down,
+}
+
+impl Side {
+    /// Det modsatte ben
+    pub fn other(self) -> Side {
+        match self {
+            Side::Up => Side::Down,
+            Side::Down => Side::Up,
+        }
+    }
+}
+
+/// Forbindelsens tilstand - udsendes på sin egen kanal ved hver overgang
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    /// Første forbindelsesforsøg er i gang
+    Connecting,
+    /// Forbundet og subscribed - bøgerne er endnu ikke bekræftet
+    Connected,
+    /// Begge tokens har leveret mindst én bogbesked - data er til at stole på
+    BooksLive,
+    /// Ingen state-ændring inden for `max_staleness` - forbindelsen kan være gået i stå
+    Stale,
+    /// Forbindelsen er tabt
+    Disconnected,
+    /// Venter på/forsøger ny forbindelse efter et tab
+    Reconnecting,
+}
+
+/// Visningsnavne for de to ben, fx Up/Down eller Yes/No.
+/// Kun presentation - `Side::Up` er altid første token, `Side::Down` det andet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Labels {
+    pub up: String,
+    pub down: String,
+}
+
+impl Labels {
+    pub fn new(up: impl Into<String>, down: impl Into<String>) -> Self {
+        Self {
+            up: up.into(),
+            down: down.into(),
+        }
+    }
+
+    pub fn get(&self, side: Side) -> &str {
+        match side {
+            Side::Up => &self.up,
+            Side::Down => &self.down,
+        }
+    }
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self::new("Up", "Down")
+    }
+}
+
+/// Feed-hændelser (livscyklus), adskilt fra state-updates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FeedEvent {
+    /// Første bog for benet er modtaget - én gang pr. ben pr. forbindelse
+    LegReady { side: Side },
+    /// Benet sendte for mange dårlige beskeder i træk og ignoreres midlertidigt
+    LegDisabled { side: Side },
+    /// Cooldown udløbet - benet behandles igen
+    LegEnabled { side: Side },
+    /// Forbindelse oprettet og subscribed
+    Connected { at_ms: i64 },
+    /// Forbindelsen er tabt - udsendes straks, før backoff/genforbindelse
+    Disconnected { at_ms: i64, reason: String },
+    /// Ny eller ændret arb-mulighed efter en state-ændring
+    ArbDetected(ArbOpportunity),
+    /// Livstegn med `heartbeat_interval` - også når bogen står stille
+    Heartbeat {
+        status: ConnectionStatus,
+        /// Millisekunder siden seneste state-ændring, None uden data
+        age_ms: Option<i64>,
+        /// Beskeder for markedet siden sidste heartbeat
+        messages: u64,
+    },
+    /// Raten af ulæselige beskeder har passeret `parse_failure_alert_rate` - typisk
+    /// ændret beskedformat eller ukendte fejl-frames. Én gang pr. spike
+    ParseFailureSpike { per_minute: u32 },
+    /// Fejl i feedet - fx mislykket connect eller ulæselig besked
+    Error(Error),
+}
+
+/// Prisskridt når markedet ikke oplyser andet (0.01)
+pub const DEFAULT_TICK_SIZE: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+
+/// Afrund `price` til nærmeste multiplum af `tick` (uændret hvis `tick` ikke er positiv)
+pub fn round_to_tick(price: Decimal, tick: Decimal) -> Decimal {
+    round_to_tick_with(price, tick, RoundingStrategy::MidpointNearestEven)
+}
+
+/// Som `round_to_tick`, med eksplicit afrundingsretning
+pub fn round_to_tick_with(price: Decimal, tick: Decimal, strategy: RoundingStrategy) -> Decimal {
+    if tick <= Decimal::ZERO {
+        return price;
+    }
+    (price / tick).round_dp_with_strategy(0, strategy) * tick
+}
+
+/// `price` med mindst lige så mange decimaler som `tick` (0.5 → 0.500 ved tick 0.001).
+/// Kun nuller tilføjes - værdien ændres aldrig
+pub fn at_tick_scale(price: Decimal, tick: Decimal) -> Decimal {
+    let mut price = price;
+    if price.scale() < tick.scale() {
+        price.rescale(tick.scale());
+    }
+    price
+}
+
+/// Hvordan afledte komplementer (`1 - p`) afrundes til tick
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ComplementRounding {
+    /// Nærmeste tick (halve ticks til lige)
+    #[default]
+    Nearest,
+    /// Altid op
+    Up,
+    /// Altid ned
+    Down,
+    /// Afledte asks (det man betaler) op og afledte bids (det man får) ned,
+    /// så et afledt komplement aldrig overdriver en edge
+    Conservative,
+}
+
+impl ComplementRounding {
+    fn strategy(self, is_ask: bool) -> RoundingStrategy {
+        match self {
+            ComplementRounding::Nearest => RoundingStrategy::MidpointNearestEven,
+            ComplementRounding::Up => RoundingStrategy::ToPositiveInfinity,
+            ComplementRounding::Down => RoundingStrategy::ToNegativeInfinity,
+            ComplementRounding::Conservative if is_ask => RoundingStrategy::ToPositiveInfinity,
+            ComplementRounding::Conservative => RoundingStrategy::ToNegativeInfinity,
+        }
+    }
+}
+
+/// Input til orderbook data layer
+#[derive(Debug, Clone)]
+pub struct OrderbookConfig {
+    pub token_up: String,
+    pub token_down: String,
+    /// Visningsnavne for benene (default Up/Down)
+    pub labels: Labels,
+    /// Afled det modsatte bens priser som `1 - p` (markeret i `OrderbookState::derived`).
+    /// Slået fra som standard, så hvert ben kun afspejler sin egen bog.
+    pub derive_complement: bool,
+    /// Maks. spread pr. ben før benet ikke stoles på (None = intet filter)
+    pub max_spread: Option<Decimal>,
+    /// Antal dårlige beskeder i træk før et ben slås fra (None = aldrig)
+    pub max_bad_messages: Option<u32>,
+    /// Hvor længe et frakoblet ben ignoreres før det prøves igen
+    pub leg_cooldown: Duration,
+    /// Resubscribe et asset når dets sekvensnumre springer over (kræver `seq` i beskederne)
+    pub resync_on_gap: bool,
+    /// Resubscribe begge tokens med dette interval (sekunder) som værn mod uopdaget drift
+    pub periodic_resync_secs: Option<u64>,
+    /// Hvor længe der ventes på første bogbesked for begge tokens før der genforbindes
+    pub subscribe_timeout: Duration,
+    /// Mindste afstand mellem to forbindelsesforsøg, uanset backoff
+    pub min_reconnect_interval: Duration,
+    /// Maks. antal `StateUpdated` pr. sekund - ændringer imellem samles (None = hver ændring)
+    pub max_update_hz: Option<u32>,
+    /// Saml ændringer og udsend højst ét `StateUpdated` pr. interval, på intervallets
+    /// tick (None = hver ændring). Har forrang for `max_update_hz`; markeder der deler
+    /// en forbindelse udsendes på det korteste af deres intervaller
+    pub coalesce_interval: Option<Duration>,
+    /// Resubscribe et token hvis dets bog har været tom så længe, mens forbindelsen
+    /// ellers lever - tvinger et frisk snapshot uden at genforbinde (None = aldrig)
+    pub empty_book_resync: Option<Duration>,
+    /// Ulæselige beskeder pr. sekund (henfaldende rate over ~1 min) der udløser
+    /// `FeedEvent::ParseFailureSpike` og en advarsel (None = kun tælling)
+    pub parse_failure_alert_rate: Option<f64>,
+    /// Maks. tid uden state-ændring før bogen meldes `Stale` (None = ingen vagt)
+    pub max_staleness: Option<Duration>,
+    /// Genforbind når bogen bliver `Stale` i stedet for blot at melde det
+    pub reconnect_on_stale: bool,
+    /// Optag hver state-ændring som JSONL til denne fil (None = ingen optagelse)
+    pub record_path: Option<PathBuf>,
+    /// Tilføj hvert `ArbDetected` med priser og vagternes tilstand til denne
+    /// revisionslog (None = ingen). Se `audit`
+    pub audit_path: Option<PathBuf>,
+    /// Send Ping med dette interval; to intervaller uden svar = død forbindelse (None = fra)
+    pub ping_interval: Option<Duration>,
+    /// Log og udsend `FeedEvent::Heartbeat` med dette interval (None = fra).
+    /// Gælder hele forbindelsen
+    pub heartbeat_interval: Option<Duration>,
+    /// Kapacitet på `StateUpdated`-kanalen - en forbruger der halter mere end dette
+    /// bagefter springer signaler over (se `FeedStats::lagged_updates`)
+    pub update_capacity: usize,
+    /// Markedets mindste prisskridt - afledte priser afrundes hertil
+    pub tick_size: Decimal,
+    /// Markedets udløb (unix-sekunder) - optagne rækker fra og med det markeres `expired`
+    pub end_ts: Option<i64>,
+    /// Afrunding af afledte komplementer: den afledte ask (1 - modsat bid) og
+    /// den afledte bid (1 - modsat ask) - se `ComplementRounding`
+    pub complement_rounding: ComplementRounding,
+    /// Hent et REST-snapshot af begge bøger ved connect med denne klient, så state
+    /// har data før første WS-besked (None = vent på WS)
+    pub seed_client: Option<reqwest::Client>,
+    /// Maks. tokens pr. subscribe-frame - flere tokens deles i så få frames som muligt
+    /// (None = alle i én). Gælder hele forbindelsen
+    pub max_assets_per_frame: Option<usize>,
+    /// WebSocket-endpoint - kan peges mod en lokal mock (gælder hele forbindelsen)
+    pub ws_url: String,
+    /// REST bog-endpoint til snapshots (token id hæftes på)
+    pub rest_book_url: String,
+    /// Kilde til vægur-tid for tidsstempler og cooldowns (se `clock::MockClock` til tests)
+    pub clock: Arc<dyn Clock>,
+    /// Abonnér også på den private user-kanal med disse nøgler (None = kun markedsdata).
+    /// Kun ved `spawn` - se `OrderbookHandle::user_state`
+    pub auth: Option<ApiCredentials>,
+    /// Endpoint for user-kanalen
+    pub user_ws_url: String,
+    /// Pauser arb-signaler (`current_arb`, `wait_for_arb`, `ArbDetected`) på mistænkelige data
+    pub circuit_breaker: CircuitBreaker,
+    /// Abonnér også på `last_trade_price` og gem seneste handel pr. ben i state.
+    /// En handel er ikke en quote - den ændrer hverken top-of-book eller udsender `StateUpdated`
+    pub track_trades: bool,
+    /// Hvor langt tilbage handler tæller med i `OrderbookHandle::estimated_fill_time`
+    pub trade_window: Duration,
+}
+
+impl Default for OrderbookConfig {
+    fn default() -> Self {
+        Self {
+            token_up: String::new(),
+            token_down: String::new(),
+            labels: Labels::default(),
+            derive_complement: false,
+            max_spread: None,
+            max_bad_messages: None,
+            leg_cooldown: Duration::from_secs(30),
+            resync_on_gap: true,
+            max_update_hz: None,
+            coalesce_interval: None,
+            empty_book_resync: None,
+            parse_failure_alert_rate: None,
+            subscribe_timeout: Duration::from_secs(10),
+            min_reconnect_interval: Duration::from_secs(2),
+            periodic_resync_secs: None,
+            max_staleness: None,
+            reconnect_on_stale: false,
+            record_path: None,
+            audit_path: None,
+            ping_interval: Some(Duration::from_secs(10)),
+            heartbeat_interval: None,
+            update_capacity: 64,
+            tick_size: DEFAULT_TICK_SIZE,
+            end_ts: None,
+            complement_rounding: ComplementRounding::default(),
+            seed_client: None,
+            max_assets_per_frame: None,
+            ws_url: DEFAULT_WS_URL.to_string(),
+            rest_book_url: market::DEFAULT_CLOB_BOOK_API.to_string(),
+            clock: clock::system(),
+            auth: None,
+            user_ws_url: user::DEFAULT_USER_WS_URL.to_string(),
+            circuit_breaker: CircuitBreaker::default(),
+            track_trades: false,
+            trade_window: Duration::from_secs(60),
+        }
+    }
+}
+
+impl OrderbookConfig {
+    /// Builder med standardværdier - `token_up` og `token_down` skal sættes
+    pub fn builder() -> OrderbookConfigBuilder {
+        OrderbookConfigBuilder {
+            config: OrderbookConfig::default(),
+        }
+    }
+
+    /// Samme config med tokens, labels og tick size fra `market`
+    pub fn with_market(self, market: &market::Market) -> Self {
+        Self {
+            token_up: market.token_up.clone(),
+            token_down: market.token_down.clone(),
+            labels: market.labels.clone(),
+            tick_size: market.tick_size,
+            end_ts: Some(market.end_ts),
+            ..self
+        }
+    }
+}
+
+/// Fluent opbygning af `OrderbookConfig`
+#[derive(Debug, Clone)]
+pub struct OrderbookConfigBuilder {
+    config: OrderbookConfig,
+}
+
+impl OrderbookConfigBuilder {
+    pub fn token_up(mut self, token: impl Into<String>) -> Self {
+        self.config.token_up = token.into();
+        self
+    }
+
+    pub fn token_down(mut self, token: impl Into<String>) -> Self {
+        self.config.token_down = token.into();
+        self
+    }
+
+    pub fn labels(mut self, labels: Labels) -> Self {
+        self.config.labels = labels;
+        self
+    }
+
+    pub fn derive_complement(mut self, derive: bool) -> Self {
+        self.config.derive_complement = derive;
+        self
+    }
+
+    pub fn max_spread(mut self, max: Decimal) -> Self {
+        self.config.max_spread = Some(max);
+        self
+    }
+
+    pub fn max_bad_messages(mut self, max: u32) -> Self {
+        self.config.max_bad_messages = Some(max);
+        self
+    }
+
+    pub fn leg_cooldown(mut self, cooldown: Duration) -> Self {
+        self.config.leg_cooldown = cooldown;
+        self
+    }
+
+    pub fn resync_on_gap(mut self, resync: bool) -> Self {
+        self.config.resync_on_gap = resync;
+        self
+    }
+
+    pub fn periodic_resync_secs(mut self, secs: u64) -> Self {
+        self.config.periodic_resync_secs = Some(secs);
+        self
+    }
+
+    pub fn subscribe_timeout(mut self, timeout: Duration) -> Self {
+        self.config.subscribe_timeout = timeout;
+        self
+    }
+
+    pub fn min_reconnect_interval(mut self, interval: Duration) -> Self {
+        self.config.min_reconnect_interval = interval;
+        self
+    }
+
+    pub fn max_update_hz(mut self, hz: u32) -> Self {
+        self.config.max_update_hz = Some(hz);
+        self
+    }
+
+    pub fn coalesce_interval(mut self, interval: Duration) -> Self {
+        self.config.coalesce_interval = Some(interval);
+        self
+    }
+
+    pub fn empty_book_resync(mut self, after: Option<Duration>) -> Self {
+        self.config.empty_book_resync = after;
+        self
+    }
+
+    pub fn parse_failure_alert_rate(mut self, per_sec: Option<f64>) -> Self {
+        self.config.parse_failure_alert_rate = per_sec;
+        self
+    }
+
+    pub fn max_staleness(mut self, max: Duration) -> Self {
+        self.config.max_staleness = Some(max);
+        self
+    }
+
+    pub fn reconnect_on_stale(mut self, reconnect: bool) -> Self {
+        self.config.reconnect_on_stale = reconnect;
+        self
+    }
+
+    pub fn record_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.record_path = Some(path.into());
+        self
+    }
+
+    pub fn audit_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.audit_path = Some(path.into());
+        self
+    }
+
+    pub fn ping_interval(mut self, interval: Option<Duration>) -> Self {
+        self.config.ping_interval = interval;
+        self
+    }
+
+    pub fn heartbeat_interval(mut self, interval: Option<Duration>) -> Self {
+        self.config.heartbeat_interval = interval;
+        self
+    }
+
+    pub fn update_capacity(mut self, capacity: usize) -> Self {
+        self.config.update_capacity = capacity;
+        self
+    }
+
+    pub fn tick_size(mut self, tick: Decimal) -> Self {
+        self.config.tick_size = tick;
+        self
+    }
+
+    pub fn complement_rounding(mut self, rounding: ComplementRounding) -> Self {
+        self.config.complement_rounding = rounding;
+        self
+    }
+
+    pub fn seed_client(mut self, client: reqwest::Client) -> Self {
+        self.config.seed_client = Some(client);
+        self
+    }
+
+    pub fn max_assets_per_frame(mut self, max: usize) -> Self {
+        self.config.max_assets_per_frame = Some(max);
+        self
+    }
+
+    pub fn ws_url(mut self, url: impl Into<String>) -> Self {
+        self.config.ws_url = url.into();
+        self
+    }
+
+    pub fn rest_book_url(mut self, url: impl Into<String>) -> Self {
+        self.config.rest_book_url = url.into();
+        self
+    }
+
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.config.clock = clock;
+        self
+    }
+
+    pub fn auth(mut self, auth: ApiCredentials) -> Self {
+        self.config.auth = Some(auth);
+        self
+    }
+
+    pub fn user_ws_url(mut self, url: impl Into<String>) -> Self {
+        self.config.user_ws_url = url.into();
+        self
+    }
+
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.config.circuit_breaker = breaker;
+        self
+    }
+
+    pub fn track_trades(mut self, track: bool) -> Self {
+        self.config.track_trades = track;
+        self
+    }
+
+    pub fn trade_window(mut self, window: Duration) -> Self {
+        self.config.trade_window = window;
+        self
+    }
+
+    /// Færdig config - fejler hvis et token mangler
+    pub fn build(self) -> Result<OrderbookConfig, MissingToken> {
+        let tokens = [
+            (Side::Up, &self.config.token_up),
+            (Side::Down, &self.config.token_down),
+        ];
+        for (side, token) in tokens {
+            if token.is_empty() {
+                return Err(MissingToken { side });
+            }
+        }
+        Ok(self.config)
+    }
+}
+
+/// Builderen mangler token for et ben
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingToken {
+    pub side: Side,
+}
+
+impl std::fmt::Display for MissingToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.side {
+            Side::Up => "token_up",
+            Side::Down => "token_down",
+        };
+        write!(f, "{} er ikke sat", name)
+    }
+}
+
+impl std::error::Error for MissingToken {}
+
+/// To markeder (indeks i config-listen) deler samme asset id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateAsset {
+    pub asset_id: String,
+    pub first: usize,
+    pub second: usize,
+}
+
+impl std::fmt::Display for DuplicateAsset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "asset {} bruges af både marked #{} og #{}",
+            self.asset_id, self.first, self.second
+        )
+    }
+}
+
+impl std::error::Error for DuplicateAsset {}
+
+/// Tjek at ingen asset id går igen på tværs af markeder (eller inden for ét),
+/// da state ellers ville blive blandet sammen
+pub fn check_unique_assets(configs: &[OrderbookConfig]) -> Result<(), DuplicateAsset> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (i, config) in configs.iter().enumerate() {
+        for asset_id in [config.token_up.as_str(), config.token_down.as_str()] {
+            if let Some(&first) = seen.get(asset_id) {
+                return Err(DuplicateAsset {
+                    asset_id: asset_id.to_string(),
+                    first,
+                    second: i,
+                });
+            }
+            seen.insert(asset_id, i);
+        }
+    }
+    Ok(())
+}
+
+/// Dybde for ét ben - bids og asks med bedste niveau først
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Depth {
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+}
+
+/// Hvad der sker når en pålidelig subscriber ikke følger med
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Kassér nye snapshots mens køen er fuld - antallet ses i `overflowed()`
+    DropNewest,
+    /// Luk abonnementet ved første overløb, så forbrugeren ved at der er et hul
+    Close,
+}
+
+/// Modtager af hvert state-snapshot via en begrænset kø.
+/// WS-loopet blokerer aldrig - ved fuld kø gælder `OverflowPolicy`.
+pub struct ReliableReceiver {
+    rx: mpsc::Receiver<OrderbookState>,
+    overflowed: Arc<AtomicU64>,
+}
+
+impl ReliableReceiver {
+    /// Næste snapshot - None når abonnementet er lukket
+    pub async fn recv(&mut self) -> Option<OrderbookState> {
+        self.rx.recv().await
+    }
+
+    /// Antal snapshots der ikke kunne leveres pga. fuld kø
+    pub fn overflowed(&self) -> u64 {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+}
+
+struct ReliableSender {
+    tx: mpsc::Sender<OrderbookState>,
+    policy: OverflowPolicy,
+    overflowed: Arc<AtomicU64>,
+}
+
+/// Bestilling af en manuel resync fra `OrderbookHandle::resync`
+struct ResyncRequest {
+    /// Markedets plads på forbindelsen
+    market: usize,
+    /// Kvitteres når resubscribe-frames er sendt
+    done: tokio::sync::oneshot::Sender<()>,
+}
+
+/// Callback for `OrderbookHandle::on_arb`
+type ArbCallback = Arc<dyn Fn(&ArbOpportunity) + Send + Sync>;
+
+/// En navngiven arb-tærskel registreret med `on_arb`
+struct ArbWatcher {
+    name: String,
+    min_edge_cents: Decimal,
+    callback: ArbCallback,
+}
+
+/// Handle til at interagere med orderbook data layer
+pub struct OrderbookHandle {
+    state: Arc<RwLock<Inner>>,
+    latest: Arc<ArcSwap<OrderbookState>>,
+    update_tx: broadcast::Sender<StateUpdated>,
+    event_tx: broadcast::Sender<FeedEvent>,
+    status_tx: broadcast::Sender<ConnectionStatus>,
+    /// Seneste status sendt på `status_tx`
+    status: Arc<Mutex<ConnectionStatus>>,
+    stats: Arc<Mutex<FeedStats>>,
+    reliable: Arc<Mutex<Vec<ReliableSender>>>,
+    watchers: Arc<Mutex<Vec<ArbWatcher>>>,
+    clock: Arc<dyn Clock>,
+    breaker: CircuitBreaker,
+    trade_window: Duration,
+    /// Kanal til forbindelsens loop og markedets plads på den (None ved replay)
+    resync: Option<(mpsc::Sender<ResyncRequest>, usize)>,
+    /// Egne ordrer og fills, kun når `auth` er sat
+    user: Option<Arc<RwLock<UserState>>>,
+    /// None for markeder i en `MultiHandle` - forbindelsen lukkes via den
+    shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    /// Baggrundstasken (None i en `MultiHandle`, der selv ejer den)
+    task: Option<JoinHandle<()>>,
+    /// Optagelsestasken, hvis `record_path` er sat
+    recorder: Option<JoinHandle<()>>,
+}
+
+impl OrderbookHandle {
+    /// Læs nuværende orderbook state
+    pub async fn get_current_state(&self) -> OrderbookState {
+        self.state.read().await.top.clone()
+    }
+
+    /// Egne åbne ordrer og fills fra user-kanalen - None uden `auth`
+    pub async fn user_state(&self) -> Option<UserState> {
+        Some(self.user.as_ref()?.read().await.clone())
+    }
+
+    /// Seneste publicerede top-of-book uden `.await` og uden at vente på skriveren -
+    /// til synkrone læsere som en render-tråd
+    pub fn latest(&self) -> Arc<OrderbookState> {
+        self.latest.load_full()
+    }
+
+    /// Aktuel arb-mulighed på rigtige bogdata, hvis nogen og breakeren ikke har pauset signaler
+    pub async fn current_arb(&self) -> Option<ArbOpportunity> {
+        let status = self.status();
+        self.breaker.detect(&self.state.read().await.top, status, self.clock.now_ms())
+    }
+
+    /// Hvorfor arb-signaler er pauset lige nu (fx "forældet data"), None hvis de ikke er
+    pub fn breaker_state(&self) -> Option<BreakerTrip> {
+        self.breaker.check(&self.latest(), self.status(), self.clock.now_ms())
+    }
+
+    /// Forbindelsens nuværende tilstand
+    pub fn status(&self) -> ConnectionStatus {
+        *self.status.lock().unwrap()
+    }
+
+    /// Arb-trin over hele dybden af begge bøger (se `arb::rungs`)
+    pub async fn arb_rungs(&self, kind: ArbKind) -> Vec<arb::Rung> {
+        let inner = self.state.read().await;
+        let (up, down) = (inner.books.get(Side::Up), inner.books.get(Side::Down));
+        match kind {
+            ArbKind::BuyBoth => arb::rungs(kind, &up.top_asks(usize::MAX), &down.top_asks(usize::MAX)),
+            ArbKind::SellBoth => arb::rungs(kind, &up.top_bids(usize::MAX), &down.top_bids(usize::MAX)),
+        }
+    }
+
+    /// Maks. parrede shares for `budget` over hele dybden af begge bøgers asks - kun
+    /// rigtige niveauer, aldrig afledte komplementer (se `sizing::max_pairs_for_budget`)
+    pub async fn max_pairs_for_budget(&self, budget: Decimal, fee_bps: Decimal) -> (Decimal, Decimal) {
+        let inner = self.state.read().await;
+        let (up, down) = (inner.books.get(Side::Up), inner.books.get(Side::Down));
+        sizing::max_pairs_for_budget(&up.top_asks(usize::MAX), &down.top_asks(usize::MAX), budget, fee_bps)
+    }
+
+    /// Vent til der er et købs-arb (`up_ask + down_ask < 1 - min_edge`) med size > 0.
+    /// Kun rigtige bogpriser tæller, aldrig afledte komplementer. Kan droppes midt
+    /// i ventetiden - abonnementet droppes med futuren.
+    pub async fn wait_for_arb(&self, min_edge: Decimal) -> ArbOpportunity {
+        // Subscribe før første tjek, så en ændring imellem ikke går tabt
+        let mut rx = self.update_tx.subscribe();
+        let min_edge_cents = min_edge * Decimal::ONE_HUNDRED;
+        loop {
+            let arb = self
+                .current_arb()
+                .await
+                .filter(|a| a.kind == ArbKind::BuyBoth && a.edge_cents > min_edge_cents);
+            if let Some(arb) = arb {
+                return arb;
+            }
+            if let Err(broadcast::error::RecvError::Closed) = rx.recv().await {
+                // Kan ikke ske mens handle lever - men spin ikke hvis det gør
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// De `levels` bedste niveauer på hver side af benets bog, bedste først
+    pub async fn get_depth(&self, side: Side, levels: usize) -> Depth {
+        let inner = self.state.read().await;
+        let book = inner.books.get(side);
+        Depth {
+            bids: book.top_bids(levels),
+            asks: book.top_asks(levels),
+        }
+    }
+
+    /// Groft estimat af hvor længe en hvilende købsordre på `size` til `price` på benet
+    /// er om at blive fyldt, ud fra handelsvolumen i det seneste `trade_window`.
+    ///
+    /// Antagelser: handler fortsætter i samme gennemsnitlige tempo som i vinduet; kun
+    /// handler til `price` eller derunder tæller, da de ville have ramt vores bid; køen
+    /// foran os på niveauet ignoreres, så hele volumen tilfalder os. Kræver `track_trades`.
+    /// None uden handler til eller gennem prisen i vinduet.
+    pub async fn estimated_fill_time(&self, side: Side, price: Decimal, size: Decimal) -> Option<Duration> {
+        let window_ms = self.trade_window.as_millis() as i64;
+        let cutoff = self.clock.now_ms() - window_ms;
+        let inner = self.state.read().await;
+        let volume: Decimal = inner
+            .books
+            .trades(side)
+            .iter()
+            .filter(|t| t.ts_ms >= cutoff && t.price <= price)
+            .map(|t| t.size)
+            .sum();
+        if volume <= Decimal::ZERO {
+            return None;
+        }
+        let ms = (size.max(Decimal::ZERO) * Decimal::from(window_ms) / volume).ceil();
+        Some(Duration::from_millis(ms.to_u64()?))
+    }
+
+    /// Subscribe til state updates
+    pub fn subscribe_updates(&self) -> broadcast::Receiver<StateUpdated> {
+        self.update_tx.subscribe()
+    }
+
+    /// Stream af kun de signaler `predicate` accepterer, fx `|u| !u.up.is_empty()` for
+    /// kun UP-ændringer. Filteret kaldes i streamen med signalet alene - ingen lås.
+    /// Halter forbrugeren, droppes de ældste signaler (talt i `lagged_updates`), så
+    /// det der leveres altid er de nyeste der matcher.
+    pub fn subscribe_filtered<F>(&self, predicate: F) -> impl Stream<Item = StateUpdated>
+    where
+        F: Fn(&StateUpdated) -> bool + Send + 'static,
+    {
+        let rx = self.update_tx.subscribe();
+        let stats = self.stats.clone();
+        let clock = self.clock.clone();
+        futures_util::stream::unfold(
+            (rx, stats, clock, predicate),
+            |(mut rx, stats, clock, predicate)| async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(update) if predicate(&update) => {
+                            return Some((update, (rx, stats, clock, predicate)));
+                        }
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            stats.lock().unwrap().lagged_updates.record_n(n, clock.now_ms());
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            },
+        )
+    }
+
+    /// Stream af frisk state ved hver ændring - til `.next().await` og stream-kombinatorer.
+    /// Halter forbrugeren bagefter, springes der direkte til nyeste state, og de
+    /// oversprungne signaler tælles i `FeedStats::lagged_updates`.
+    pub fn updates_stream(&self) -> impl Stream<Item = OrderbookState> {
+        let rx = self.update_tx.subscribe();
+        let state = self.state.clone();
+        let stats = self.stats.clone();
+        let clock = self.clock.clone();
+        futures_util::stream::unfold((rx, state, stats, clock), |(mut rx, state, stats, clock)| async move {
+            let record_lag = |n: u64| {
+                stats.lock().unwrap().lagged_updates.record_n(n, clock.now_ms());
+            };
+            match rx.recv().await {
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(n)) => record_lag(n),
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+            // Ticks der allerede ligger i kø beskriver ældre ændringer - state er nyere
+            loop {
+                match rx.try_recv() {
+                    Ok(_) => {}
+                    Err(broadcast::error::TryRecvError::Lagged(n)) => record_lag(n),
+                    Err(_) => break,
+                }
+            }
+            let snapshot = state.read().await.top.clone();
+            Some((snapshot, (rx, state, stats, clock)))
+        })
+    }
+
+    /// Kør `f` som akkumulator over hver state-ændring (samme semantik som `updates_stream`:
+    /// ved lag anvendes kun nyeste state). Futuren ejer sit abonnement og slutter med
+    /// akkumulatoren når feedet lukkes; droppes den før, er akkumulatoren tabt.
+    /// Til udløb: kør den i `select!` mod en sleep til `end_ts`.
+    pub fn fold_updates<T, F>(&self, init: T, mut f: F) -> impl std::future::Future<Output = T>
+    where
+        F: FnMut(T, &OrderbookState) -> T,
+    {
+        let updates = self.updates_stream();
+        async move {
+            let mut updates = std::pin::pin!(updates);
+            let mut acc = init;
+            while let Some(state) = updates.next().await {
+                acc = f(acc, &state);
+            }
+            acc
+        }
+    }
+
+    /// Subscribe til hvert state-snapshot uden broadcast-lag, med en kø på `capacity`.
+    /// Til forbrugere der ikke må misse updates (fx recorder); lossy visning bør bruge
+    /// `subscribe_updates`.
+    pub fn subscribe_reliable(&self, capacity: usize, policy: OverflowPolicy) -> ReliableReceiver {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        let overflowed = Arc::new(AtomicU64::new(0));
+        self.reliable.lock().unwrap().push(ReliableSender {
+            tx,
+            policy,
+            overflowed: overflowed.clone(),
+        });
+        ReliableReceiver { rx, overflowed }
+    }
+
+    /// Tving et frisk snapshot for begge tokens uden at genforbinde: niveau-bøgerne
+    /// ryddes, og tokens afmeldes og subscribes igen. Returnerer når frames er sendt
+    /// (eller straks ved replay og efter nedlukning). Kan kaldes gentagne gange.
+    pub async fn resync(&self) {
+        let Some((tx, market)) = &self.resync else { return };
+        let (done, sent) = tokio::sync::oneshot::channel();
+        if tx.send(ResyncRequest { market: *market, done }).await.is_ok() {
+            let _ = sent.await;
+        }
+    }
+
+    /// Kald `callback` ved hver state-ændring hvor der er et arb med edge over `min_edge`
+    /// (samme enhed som `wait_for_arb`). Tærsklerne evalueres samlet i orderbook-tasken
+    /// efter circuit breakeren; et eksisterende watcher med samme navn erstattes.
+    /// Callbacken kører på tasken og skal være hurtig - send tungt arbejde videre.
+    pub fn on_arb<F>(&self, name: impl Into<String>, min_edge: Decimal, callback: F)
+    where
+        F: Fn(&ArbOpportunity) + Send + Sync + 'static,
+    {
+        let name = name.into();
+        let watcher = ArbWatcher {
+            name: name.clone(),
+            min_edge_cents: min_edge * Decimal::ONE_HUNDRED,
+            callback: Arc::new(callback),
+        };
+        let mut watchers = self.watchers.lock().unwrap();
+        match watchers.iter_mut().find(|w| w.name == name) {
+            Some(existing) => *existing = watcher,
+            None => watchers.push(watcher),
+        }
+    }
+
+    /// Fjern watcheren med dette navn - false hvis der ingen var
+    pub fn remove_arb_watcher(&self, name: &str) -> bool {
+        let mut watchers = self.watchers.lock().unwrap();
+        let before = watchers.len();
+        watchers.retain(|w| w.name != name);
+        watchers.len() != before
+    }
+
+    /// Driftstællere (kumulativt og henfaldende rate)
+    pub fn stats(&self) -> FeedStats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// Forsinkelse fra børs-timestamp til state (seneste, p50, p99 over vinduet).
+    /// Høje tal betyder at vi halter efter markedet og ikke bør handle
+    pub fn latency(&self) -> LatencyWindow {
+        self.stats.lock().unwrap().latency
+    }
+
+    /// Subscribe til forbindelsens tilstandsovergange
+    pub fn subscribe_status(&self) -> broadcast::Receiver<ConnectionStatus> {
+        self.status_tx.subscribe()
+    }
+
+    /// Subscribe til feed-hændelser (fx `LegReady`)
+    pub fn subscribe_events(&self) -> broadcast::Receiver<FeedEvent> {
+        self.event_tx.subscribe()
+    }
+
+    /// Stop orderbook data layer og vent til loopet har afmeldt sig og lukket
+    /// forbindelsen, og en evt. optagelse er skrevet færdig
+    pub async fn shutdown(self) {
+        let tasks = self.into_tasks();
+        if let Some(tx) = tasks.shutdown_tx {
+            let _ = tx.send(());
+        }
+        join(tasks.task).await;
+        // Optagelsen slutter først når alle afsendere er droppet - også handlens
+        join(tasks.recorder).await;
+    }
+
+    /// Skil tasks fra handle; resten (kanaler og state) droppes her
+    fn into_tasks(mut self) -> Tasks {
+        Tasks {
+            shutdown_tx: self.shutdown_tx.take(),
+            task: self.task.take(),
+            recorder: self.recorder.take(),
+        }
+    }
+}
+
+/// En handle der droppes uden `shutdown()` stopper stadig forbindelsen - tasken
+/// lukker pænt ned i baggrunden, men afventes ikke
+impl Drop for OrderbookHandle {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// Det af en handle der skal bruges til at stoppe og afvente dens tasks
+struct Tasks {
+    shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    task: Option<JoinHandle<()>>,
+    recorder: Option<JoinHandle<()>>,
+}
+
+async fn join(task: Option<JoinHandle<()>>) {
+    if let Some(task) = task {
+        if let Err(e) = task.await {
+            warn!(error = %e, "task afsluttede med fejl");
+        }
+    }
+}
+
+/// Et markeds plads i `MultiHandle` - samme rækkefølge som configs til `spawn_multi`
+pub type MarketId = usize;
+
+/// Handle til flere markeder der deler én WebSocket-forbindelse
+pub struct MultiHandle {
+    markets: Vec<OrderbookHandle>,
+    /// Token id -> indeks i `markets`
+    by_token: HashMap<String, usize>,
+    shutdown_tx: tokio::sync::oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl MultiHandle {
+    /// Antal markeder
+    pub fn len(&self) -> usize {
+        self.markets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markets.is_empty()
+    }
+
+    /// Marked nr. `index` i den rækkefølge configs blev givet
+    pub fn market(&self, index: usize) -> Option<&OrderbookHandle> {
+        self.markets.get(index)
+    }
+
+    /// Markedet som handler `token_id` (enten UP eller DOWN)
+    pub fn market_by_token(&self, token_id: &str) -> Option<&OrderbookHandle> {
+        self.market(*self.by_token.get(token_id)?)
+    }
+
+    /// Læs nuværende state for marked nr. `index`
+    pub async fn get_current_state(&self, index: usize) -> Option<OrderbookState> {
+        Some(self.market(index)?.get_current_state().await)
+    }
+
+    /// Seneste top-of-book for marked nr. `index` uden `.await` (se `OrderbookHandle::latest`)
+    pub fn latest(&self, index: usize) -> Option<Arc<OrderbookState>> {
+        Some(self.market(index)?.latest())
+    }
+
+    /// Markeder med en arb lige nu som (marked, edge i cents, size), bedste edge først.
+    /// Bygger på seneste top-of-book; markeder hvor breakeren har pauset signaler er udeladt.
+    pub fn ranked_opportunities(&self) -> Vec<(MarketId, f64, Decimal)> {
+        let mut ranked: Vec<_> = self
+            .markets
+            .iter()
+            .enumerate()
+            .filter_map(|(id, m)| {
+                let arb = m.breaker.detect(&m.latest(), m.status(), m.clock.now_ms())?;
+                Some((id, arb.edge_cents.to_f64().unwrap_or(0.0), arb.size))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(b.2.cmp(&a.2)));
+        ranked
+    }
+
+    /// Stop forbindelsen for alle markeder og vent til den er lukket
+    pub async fn shutdown(self) {
+        let _ = self.shutdown_tx.send(());
+        join(Some(self.task)).await;
+        let recorders: Vec<_> = self.markets.into_iter().map(|m| m.into_tasks().recorder).collect();
+        for recorder in recorders {
+            join(recorder).await;
+        }
+    }
+}
+
+/// Kø-størrelse for optagelse - rigeligt til at dække et langsomt flush
+const RECORDER_CAPACITY: usize = 1024;
+
+/// Ventende `resync()`-kald pr. forbindelse
+const RESYNC_CAPACITY: usize = 8;
+
+/// Start orderbook data layer - returnerer handle til interaktion
+pub fn spawn(config: OrderbookConfig) -> OrderbookHandle {
+    let (shared, mut handle, shutdown_rx) = start(&config);
+    let (resync_tx, resync_rx) = mpsc::channel(RESYNC_CAPACITY);
+    handle.resync = Some((resync_tx, 0));
+
+    // User-kanalen lever og dør med bog-tasken
+    let user_task = config.auth.clone().map(|auth| {
+        let state = Arc::new(RwLock::new(UserState::default()));
+        handle.user = Some(state.clone());
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+        let task = tokio::spawn(user::run(config.user_ws_url.clone(), auth, state, stop_rx));
+        (stop_tx, task)
+    });
+
+    handle.task = Some(tokio::spawn(async move {
+        run_websocket_loop(vec![Feed { config, shared }], shutdown_rx, resync_rx).await;
+        if let Some((stop_tx, task)) = user_task {
+            let _ = stop_tx.send(());
+            join(Some(task)).await;
+        }
+    }));
+    handle
+}
+
+/// Start ét orderbook data layer for flere markeder over én WebSocket-forbindelse.
+/// Hver besked routes til sit marked via asset id, så intet token må gå igen.
+/// Forbindelsesindstillinger tages fra første config.
+pub fn spawn_multi(configs: Vec<OrderbookConfig>) -> Result<MultiHandle, DuplicateAsset> {
+    check_unique_assets(&configs)?;
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    let (resync_tx, resync_rx) = mpsc::channel(RESYNC_CAPACITY);
+
+    let mut feeds = Vec::new();
+    let mut markets = Vec::new();
+    let mut by_token = HashMap::new();
+    for (i, config) in configs.into_iter().enumerate() {
+        let (shared, mut handle) = channels(&config);
+        handle.resync = Some((resync_tx.clone(), i));
+        by_token.insert(config.token_up.clone(), i);
+        by_token.insert(config.token_down.clone(), i);
+        feeds.push(Feed { config, shared });
+        markets.push(handle);
+    }
+
+    let task = tokio::spawn(async move {
+        run_websocket_loop(feeds, shutdown_rx, resync_rx).await;
+    });
+
+    Ok(MultiHandle {
+        markets,
+        by_token,
+        shutdown_tx,
+        task,
+    })
+}
+
+/// Hent top-of-book én gang uden en langlivet handle: forbind, vent til begge ben
+/// har data (WS-bog eller REST-seed), og luk ned igen.
+///
+/// Tokens tages fra `config` - brug `OrderbookConfig::with_market` efter discovery.
+/// Når bøgerne ikke frem inden `timeout`, fås `Error::Timeout` med seneste feed-fejl.
+pub async fn fetch_book_once(config: OrderbookConfig, timeout: Duration) -> Result<OrderbookState, Error> {
+    let handle = spawn(config);
+    let mut updates = handle.subscribe_updates();
+    let mut events = handle.subscribe_events();
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+
+    let has_data = |bid: Option<Decimal>, ask: Option<Decimal>| bid.is_some() || ask.is_some();
+    let ready = |s: &OrderbookState| has_data(s.up_bid_price, s.up_ask_price) && has_data(s.down_bid_price, s.down_ask_price);
+    let mut last_error = None;
+    let result = loop {
+        tokio::select! {
+            _ = &mut deadline => {
+                let reason = match &last_error {
+                    Some(e) => format!("ingen bøger efter {:?} (seneste fejl: {})", timeout, e),
+                    None => format!("ingen bøger efter {:?}", timeout),
+                };
+                break Err(Error::Timeout(reason));
+            }
+            Ok(FeedEvent::Error(e)) = events.recv() => last_error = Some(e),
+            // Lag er ligegyldigt - state tjekkes direkte herunder. Lukket kanal betyder
+            // at intet nyt kommer, så svar med det vi har i stedet for at spinne
+            res = updates.recv() => if let Err(broadcast::error::RecvError::Closed) = res {
+                let state = handle.latest();
+                if ready(&state) {
+                    break Ok((*state).clone());
+                }
+                let reason = last_error.map_or_else(|| "feedet stoppede".to_string(), |e| e.to_string());
+                break Err(Error::ClosedBeforeReady(reason));
+            },
+        }
+        let state = handle.latest();
+        if ready(&state) {
+            break Ok((*state).clone());
+        }
+    };
+
+    handle.shutdown().await;
+    result
+}
+
+/// Tempo for afspilning af en optagelse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayPacing {
+    /// Vent mellem records som de optagne tidsstempler angiver
+    RealTime,
+    /// Afspil så hurtigt som muligt - til deterministiske tests
+    AsFastAsPossible,
+}
+
+/// Start orderbook data layer fra en optagelse (se `recorder`) i stedet for WebSocket.
+///
+/// Handle opfører sig som ved `spawn`. Er tokens sat i `config`, afspilles kun
+/// records for det token-par. Kun top-of-book er optaget, så `get_depth` er tom.
+/// Afspilningen starter først når kalderen giver kontrollen fra sig, så
+/// subscriptions oprettet lige efter kaldet ser alle records.
+pub fn spawn_replay(
+    path: impl Into<PathBuf>,
+    config: OrderbookConfig,
+    pacing: ReplayPacing,
+) -> OrderbookHandle {
+    let path = path.into();
+    let (shared, mut handle, shutdown_rx) = start(&config);
+    handle.task = Some(tokio::spawn(async move {
+        tokio::task::yield_now().await;
+        run_replay(path, config, pacing, shared, shutdown_rx).await;
+    }));
+    handle
+}
+
+/// Kanaler og shutdown for et selvstændigt marked - fælles for live og replay
+fn start(config: &OrderbookConfig) -> (Shared, OrderbookHandle, tokio::sync::oneshot::Receiver<()>) {
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    let (shared, mut handle) = channels(config);
+    handle.shutdown_tx = Some(shutdown_tx);
+    (shared, handle, shutdown_rx)
+}
+
+/// Kanaler, delt state og evt. optagelse for ét marked
+fn channels(config: &OrderbookConfig) -> (Shared, OrderbookHandle) {
+    let state = Arc::new(RwLock::new(Inner::default()));
+    let latest = Arc::new(ArcSwap::from_pointee(OrderbookState::default()));
+    let (update_tx, _) = broadcast::channel(config.update_capacity.max(1));
+    let (event_tx, _) = broadcast::channel(16);
+    let (status_tx, _) = broadcast::channel(16);
+    let status = Arc::new(Mutex::new(ConnectionStatus::Connecting));
+    let stats = Arc::new(Mutex::new(FeedStats::default()));
+    let reliable = Arc::new(Mutex::new(Vec::new()));
+    let watchers = Arc::new(Mutex::new(Vec::new()));
+    let audit = config.audit_path.as_ref().and_then(|path| {
+        match AuditLog::open(path, &config.token_up, &config.token_down) {
+            Ok(log) => Some(Arc::new(Mutex::new(log))),
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "kan ikke åbne revisionslog");
+                None
+            }
+        }
+    });
+
+    let mut recorder = None;
+    if let Some(path) = config.record_path.clone() {
+        let (tx, rx) = mpsc::channel(RECORDER_CAPACITY);
+        let overflowed = Arc::new(AtomicU64::new(0));
+        reliable.lock().unwrap().push(ReliableSender {
+            tx,
+            policy: OverflowPolicy::DropNewest,
+            overflowed: overflowed.clone(),
+        });
+        let rx = ReliableReceiver { rx, overflowed };
+        let (up, down) = (config.token_up.clone(), config.token_down.clone());
+        recorder = Some(tokio::spawn(recorder::run(path, rx, up, down, config.end_ts)));
+    }
+
+    let shared = Shared {
+        state: state.clone(),
+        latest: latest.clone(),
+        update_tx: update_tx.clone(),
+        event_tx: event_tx.clone(),
+        status_tx: status_tx.clone(),
+        status: status.clone(),
+        stats: stats.clone(),
+        reliable: reliable.clone(),
+        watchers: watchers.clone(),
+        clock: config.clock.clone(),
+        audit,
+    };
+
+    let handle = OrderbookHandle {
+        state,
+        latest,
+        update_tx,
+        event_tx,
+        status_tx,
+        status,
+        stats,
+        reliable,
+        watchers,
+        clock: config.clock.clone(),
+        breaker: config.circuit_breaker,
+        trade_window: config.trade_window,
+        resync: None,
+        user: None,
+        shutdown_tx: None,
+        task: None,
+        recorder,
+    };
+    (shared, handle)
+}
+
+/// Afspil records fra `path` som var de live updates
+async fn run_replay(
+    path: PathBuf,
+    config: OrderbookConfig,
+    pacing: ReplayPacing,
+    shared: Shared,
+    mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+) {
+    shared.set_status(ConnectionStatus::Connecting);
+    let records = match recorder::read(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            let error = Error::Replay(format!("{}: {}", path.display(), e));
+            let _ = shared.event_tx.send(FeedEvent::Error(error));
+            shared.set_status(ConnectionStatus::Disconnected);
+            return;
+        }
+    };
+    let at_ms = shared.clock.now_ms();
+    let _ = shared.event_tx.send(FeedEvent::Connected { at_ms });
+    shared.set_status(ConnectionStatus::Connected);
+
+    let filter = !config.token_up.is_empty() || !config.token_down.is_empty();
+    let mut clock: Option<(i64, Instant)> = None;
+    let mut up_ready = false;
+    let mut down_ready = false;
+    let mut last_arb: Option<ArbOpportunity> = None;
+
+    for record in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let _ = shared.event_tx.send(FeedEvent::Error(Error::Replay(e.to_string())));
+                continue;
+            }
+        };
+        if filter && (record.token_up != config.token_up || record.token_down != config.token_down) {
+            continue;
+        }
+
+        match pacing {
+            ReplayPacing::RealTime => {
+                let (first_ts, started) = *clock.get_or_insert((record.ts_ms, Instant::now()));
+                let offset = Duration::from_millis((record.ts_ms - first_ts).max(0) as u64);
+                tokio::select! {
+                    _ = &mut shutdown_rx => return,
+                    _ = tokio::time::sleep_until(started + offset) => {}
+                }
+            }
+            ReplayPacing::AsFastAsPossible => {
+                // Giv forbrugerne en chance for at følge med
+                tokio::task::yield_now().await;
+                if !matches!(shutdown_rx.try_recv(), Err(tokio::sync::oneshot::error::TryRecvError::Empty)) {
+                    return;
+                }
+            }
+        }
+
+        let r = record.state;
+        let (arb, change, snapshot, status) = {
+            let mut inner = shared.state.write().await;
+            let top = &mut inner.top;
+            let before = top.clone();
+            top.up_bid_price = r.up_bid_price;
+            top.up_bid_size = r.up_bid_size;
+            top.up_ask_price = r.up_ask_price;
+            top.up_ask_size = r.up_ask_size;
+            top.down_bid_price = r.down_bid_price;
+            top.down_bid_size = r.down_bid_size;
+            top.down_ask_price = r.down_ask_price;
+            top.down_ask_size = r.down_ask_size;
+            top.last_update_ms = record.ts_ms;
+            top.up_last_message_ms = record.ts_ms;
+            top.down_last_message_ms = record.ts_ms;
+            top.up_wide_spread = is_wide(top.up_spread(), config.max_spread);
+            top.down_wide_spread = is_wide(top.down_spread(), config.max_spread);
+            let snapshot = Arc::new(top.clone());
+            shared.latest.store(snapshot.clone());
+            // Alder måles mod optagelsens egen tid - afspilningen er ikke forældet data
+            let status = *shared.status.lock().unwrap();
+            let arb = config.circuit_breaker.detect(top, status, top.last_update_ms);
+            (arb, StateUpdated::between(&before, top), snapshot, status)
+        };
+
+        if let Some(opportunity) = arb.filter(|_| arb != last_arb) {
+            shared.signal_arb(opportunity, &snapshot, status, record.ts_ms);
+        }
+        last_arb = arb;
+        if let Some(opportunity) = &arb {
+            shared.notify_watchers(opportunity);
+        }
+
+        if !change.is_empty() {
+            shared.deliver_reliable().await;
+            let _ = shared.update_tx.send(change);
+        }
+
+        let has_up = r.up_bid_price.is_some() || r.up_ask_price.is_some();
+        let has_down = r.down_bid_price.is_some() || r.down_ask_price.is_some();
+        let was_live = up_ready && down_ready;
+        for (side, has, ready) in [(Side::Up, has_up, &mut up_ready), (Side::Down, has_down, &mut down_ready)] {
+            if has && !*ready {
+                *ready = true;
+                let _ = shared.event_tx.send(FeedEvent::LegReady { side });
+            }
+        }
+        if !was_live && up_ready && down_ready {
+            shared.set_status(ConnectionStatus::BooksLive);
+        }
+    }
+
+    // Optagelsen er slut - meld det som en tabt forbindelse; state kan stadig læses
+    let at_ms = shared.clock.now_ms();
+    let reason = "replay afsluttet".to_string();
+    let _ = shared.event_tx.send(FeedEvent::Disconnected { at_ms, reason });
+    shared.set_status(ConnectionStatus::Disconnected);
+}
+
+/// Top-of-book og de fulde bøger bag én lås, så de altid er konsistente
+#[derive(Default)]
+struct Inner {
+    top: OrderbookState,
+    books: Books,
+}
+
+/// State og kanaler som baggrundstasken deler med handle
+struct Shared {
+    state: Arc<RwLock<Inner>>,
+    /// Kopi af `top` publiceret efter hver skrivning - læses lock-frit af `latest()`
+    latest: Arc<ArcSwap<OrderbookState>>,
+    update_tx: broadcast::Sender<StateUpdated>,
+    event_tx: broadcast::Sender<FeedEvent>,
+    status_tx: broadcast::Sender<ConnectionStatus>,
+    status: Arc<Mutex<ConnectionStatus>>,
+    stats: Arc<Mutex<FeedStats>>,
+    reliable: Arc<Mutex<Vec<ReliableSender>>>,
+    watchers: Arc<Mutex<Vec<ArbWatcher>>>,
+    clock: Arc<dyn Clock>,
+    /// Revisionslog for arb-signaler, hvis `audit_path` er sat
+    audit: Option<Arc<Mutex<AuditLog>>>,
+}
+
+impl Shared {
+    /// Udsend `ArbDetected` - skrives først til revisionsloggen, så intet signal
+    /// går ud uden at være logget
+    fn signal_arb(&self, opportunity: ArbOpportunity, state: &OrderbookState, status: ConnectionStatus, now_ms: i64) {
+        if let Some(audit) = &self.audit {
+            if let Err(e) = audit.lock().unwrap().append(&opportunity, state, status, now_ms) {
+                warn!(error = %e, "kan ikke skrive til revisionslog");
+            }
+        }
+        let _ = self.event_tx.send(FeedEvent::ArbDetected(opportunity));
+    }
+
+    /// Kald de watchers hvis tærskel `opportunity` overstiger. Callbacks kaldes uden
+    /// låsen, så de selv kan registrere eller fjerne watchers
+    fn notify_watchers(&self, opportunity: &ArbOpportunity) {
+        let matching: Vec<ArbCallback> = self
+            .watchers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|w| opportunity.edge_cents > w.min_edge_cents)
+            .map(|w| w.callback.clone())
+            .collect();
+        for callback in matching {
+            callback(opportunity);
+        }
+    }
+
+    fn set_status(&self, status: ConnectionStatus) {
+        *self.status.lock().unwrap() = status;
+        let _ = self.status_tx.send(status);
+    }
+
+    /// Lever et snapshot til pålidelige subscribers uden at blokere
+    async fn deliver_reliable(&self) {
+        if self.reliable.lock().unwrap().is_empty() {
+            return;
+        }
+        let snapshot = self.state.read().await.top.clone();
+        let now_ms = self.clock.now_ms();
+
+        let mut dropped = 0;
+        self.reliable.lock().unwrap().retain(|sub| match sub.tx.try_send(snapshot.clone()) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                dropped += 1;
+                sub.overflowed.fetch_add(1, Ordering::Relaxed);
+                sub.policy == OverflowPolicy::DropNewest
+            }
+        });
+        if dropped > 0 {
+            self.stats.lock().unwrap().dropped_updates.record_n(dropped, now_ms);
+        }
+    }
+}
+
+/// Hvorfor en forbindelse sluttede
+enum ConnectionEnd {
+    Shutdown,
+    /// `ClosedBeforeReady` eller `ConnectionLost`
+    Lost(Error),
+}
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Maks. tid til afmelding og Close-frame ved shutdown, så en død socket ikke hænger
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Ét marked på forbindelsen - dets config og de kanaler dets handle lytter på
+struct Feed {
+    config: OrderbookConfig,
+    shared: Shared,
+}
+
+/// Supervisor - genforbinder med eksponentiel backoff indtil shutdown.
+/// Forbindelsesindstillinger (reconnect-loft, subscribe-timeout, periodisk resync,
+/// frame-størrelse, heartbeat) tages fra første marked.
+async fn run_websocket_loop(
+    feeds: Vec<Feed>,
+    mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+    mut resync_rx: mpsc::Receiver<ResyncRequest>,
+) {
+    let Some(first) = feeds.first() else { return };
+    let min_reconnect_interval = first.config.min_reconnect_interval;
+    let clock = first.shared.clock.clone();
+    let mut backoff = INITIAL_BACKOFF;
+    let mut last_attempt: Option<Instant> = None;
+    let mut attempt = 0u64;
+    let tokens = feeds
+        .iter()
+        .flat_map(|f| [f.config.token_up.as_str(), f.config.token_down.as_str()])
+        .collect::<Vec<_>>()
+        .join(",");
+    for feed in &feeds {
+        feed.shared.set_status(ConnectionStatus::Connecting);
+    }
+
+    loop {
+        // Hårdt loft over forsøgsfrekvensen, så en reconnect-storm ikke throttler IP'en
+        if let Some(last) = last_attempt {
+            let earliest = last + min_reconnect_interval;
+            if Instant::now() < earliest {
+                let now_ms = clock.now_ms();
+                for feed in &feeds {
+                    feed.shared.stats.lock().unwrap().reconnect_floor_delays.record(now_ms);
+                }
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    _ = tokio::time::sleep_until(earliest) => {}
+                }
+            }
+        }
+        last_attempt = Some(Instant::now());
+        attempt += 1;
+
+        let span = info_span!("connection", attempt, tokens = %tokens);
+        match run_connection(&feeds, &mut shutdown_rx, &mut resync_rx).instrument(span).await {
+            Ok(ConnectionEnd::Shutdown) => break,
+            Ok(ConnectionEnd::Lost(error)) => {
+                // Forbindelsen var oppe - meld tabet før backoff, og start forfra
+                let at_ms = clock.now_ms();
+                #[cfg(feature = "metrics")]
+                metrics::record_reconnect();
+                warn!(attempt, error = %error, "forbindelse tabt - genforbinder");
+                let reason = error.to_string();
+                for feed in &feeds {
+                    let _ = feed.shared.event_tx.send(FeedEvent::Error(error.clone()));
+                    let reason = reason.clone();
+                    let _ = feed.shared.event_tx.send(FeedEvent::Disconnected { at_ms, reason });
+                    feed.shared.set_status(ConnectionStatus::Disconnected);
+                    feed.shared.stats.lock().unwrap().reconnects.record(at_ms);
+                }
+                // Rate-limit/policy-lukning: start ved loftet i stedet for forfra
+                backoff = if error.is_throttled() { MAX_BACKOFF } else { INITIAL_BACKOFF };
+            }
+            Err(error) => {
+                warn!(attempt, error = %error, ?backoff, "forbindelse fejlede");
+                for feed in &feeds {
+                    let _ = feed.shared.event_tx.send(FeedEvent::Error(error.clone()));
+                }
+            }
+        }
+
+        for feed in &feeds {
+            feed.shared.set_status(ConnectionStatus::Reconnecting);
+        }
+        tokio::select! {
+            _ = &mut shutdown_rx => break,
+            _ = tokio::time::sleep(backoff) => {}
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Tabt forbindelse - `ClosedBeforeReady` hvis et marked endnu ikke havde begge bøger
+fn lost(conns: &[FeedConn], reason: String) -> ConnectionEnd {
+    if conns.iter().all(FeedConn::live) {
+        ConnectionEnd::Lost(Error::ConnectionLost(reason))
+    } else {
+        ConnectionEnd::Lost(Error::ClosedBeforeReady(reason))
+    }
+}
+
+/// Et markeds tilstand på den aktuelle forbindelse
+struct FeedConn {
+    /// Ben der har leveret deres første bog på denne forbindelse
+    up_ready: bool,
+    down_ready: bool,
+    /// Tokens der har svaret på subscribe med mindst én bogbesked
+    up_seen: bool,
+    down_seen: bool,
+    /// Sidst udsendte arb, så samme mulighed ikke meldes ved hver tick
+    last_arb: Option<ArbOpportunity>,
+    /// Coalescing: mindste afstand mellem signaler og den samlede ændring der venter.
+    /// Med `coalesce` udsendes `pending` kun på loopets coalesce-tick - `Some` er dirty
+    min_interval: Option<Duration>,
+    coalesce: bool,
+    last_emit: Option<Instant>,
+    pending: Option<StateUpdated>,
+    /// Staleness-vagt - armeres først når bøgerne er live; før det dækker `subscribe_timeout`
+    last_change: Instant,
+    stale: bool,
+    /// Siden hvornår benets bog har været tom (eller sidste resubscribe pga. det)
+    up_empty_since: Option<Instant>,
+    down_empty_since: Option<Instant>,
+    /// `ParseFailureSpike` er meldt og raten er endnu ikke faldet under tærsklen
+    parse_spike: bool,
+    /// Beskeder routet til markedet siden sidste heartbeat
+    heartbeat_messages: u64,
+}
+
+impl FeedConn {
+    fn new(config: &OrderbookConfig) -> Self {
+        Self {
+            up_ready: false,
+            down_ready: false,
+            up_seen: false,
+            down_seen: false,
+            last_arb: None,
+            min_interval: config
+                .max_update_hz
+                .filter(|hz| *hz > 0)
+                .map(|hz| Duration::from_secs_f64(1.0 / hz as f64)),
+            coalesce: coalesce_period(config).is_some(),
+            last_emit: None,
+            pending: None,
+            last_change: Instant::now(),
+            stale: false,
+            up_empty_since: None,
+            down_empty_since: None,
+            parse_spike: false,
+            heartbeat_messages: 0,
+        }
+    }
+
+    fn live(&self) -> bool {
+        self.up_seen && self.down_seen
+    }
+
+    /// Hvornår benet skal meldes forældet, hvis vagten er armeret
+    fn stale_deadline(&self, config: &OrderbookConfig) -> Option<Instant> {
+        let max = config.max_staleness?;
+        (self.live() && !self.stale).then_some(self.last_change + max)
+    }
+
+    fn empty_since_mut(&mut self, side: Side) -> &mut Option<Instant> {
+        match side {
+            Side::Up => &mut self.up_empty_since,
+            Side::Down => &mut self.down_empty_since,
+        }
+    }
+
+    /// Hvornår et tomt ben skal resubscribes, og hvilket
+    fn empty_deadline(&self, config: &OrderbookConfig) -> Option<(Instant, Side)> {
+        let after = config.empty_book_resync?;
+        [(self.up_empty_since, Side::Up), (self.down_empty_since, Side::Down)]
+            .into_iter()
+            .filter_map(|(since, side)| Some((since? + after, side)))
+            .min_by_key(|(at, _)| *at)
+    }
+
+    /// Hvornår en ventende ændring skal udsendes - ved coalescing først på næste tick
+    fn emit_deadline(&self) -> Option<Instant> {
+        if self.coalesce {
+            return None;
+        }
+        self.pending.map(|_| next_emit(self.last_emit, self.min_interval).unwrap_or_else(Instant::now))
+    }
+}
+
+/// Én WebSocket-forbindelse for alle markeder: connect, subscribe og event loop.
+/// Err betyder at forbindelsen aldrig kom op.
+async fn run_connection(
+    feeds: &[Feed],
+    shutdown_rx: &mut tokio::sync::oneshot::Receiver<()>,
+    resync_rx: &mut mpsc::Receiver<ResyncRequest>,
+) -> Result<ConnectionEnd, Error> {
+    let conn_config = &feeds[0].config;
+
+    // Asset id -> (marked, ben)
+    let mut route: HashMap<&str, (usize, Side)> = HashMap::new();
+    for (i, feed) in feeds.iter().enumerate() {
+        route.insert(feed.config.token_up.as_str(), (i, Side::Up));
+        route.insert(feed.config.token_down.as_str(), (i, Side::Down));
+    }
+    let all_assets: Vec<&str> = feeds
+        .iter()
+        .flat_map(|f| [f.config.token_up.as_str(), f.config.token_down.as_str()])
+        .collect();
+    let trade_assets: Vec<&str> = feeds
+        .iter()
+        .filter(|f| f.config.track_trades)
+        .flat_map(|f| [f.config.token_up.as_str(), f.config.token_down.as_str()])
+        .collect();
+
+    // Forbind til WebSocket
+    let (ws, _) = connect_async(conn_config.ws_url.as_str())
+        .await
+        .map_err(|e| Error::Connect(e.to_string()))?;
+    info!(url = %conn_config.ws_url, "forbundet");
+
+    let (mut write, mut read) = ws.split();
+
+    // Subscribe til alle tokens i så få frames som `max_assets_per_frame` tillader.
+    // Routing sker pr. asset id, så opdelingen betyder intet for beskederne
+    let per_frame = conn_config.max_assets_per_frame.filter(|n| *n > 0).unwrap_or(usize::MAX);
+    for batch in all_assets.chunks(per_frame) {
+        if let Err(e) = write.send(book_frame("subscribe", batch)).await {
+            return Err(Error::Subscribe(format!("{} tokens: {}", batch.len(), e)));
+        }
+    }
+    for batch in trade_assets.chunks(per_frame) {
+        if let Err(e) = write.send(channel_frame("subscribe", TRADE_CHANNEL, batch)).await {
+            return Err(Error::Subscribe(format!("handler for {} tokens: {}", batch.len(), e)));
+        }
+    }
+    debug!(tokens = all_assets.len(), frames = all_assets.len().div_ceil(per_frame), "subscribe sendt");
+
+    let clock = &feeds[0].shared.clock;
+    let at_ms = clock.now_ms();
+    for feed in feeds {
+        let _ = feed.shared.event_tx.send(FeedEvent::Connected { at_ms });
+        feed.shared.set_status(ConnectionStatus::Connected);
+    }
+
+    let mut conns: Vec<FeedConn> = feeds.iter().map(|f| FeedConn::new(&f.config)).collect();
+
+    // REST-snapshots hentes sideløbende med WS og anvendes kun på ben uden live data endnu
+    let (seed_tx, mut seed_rx) = mpsc::channel::<Seed>(8);
+    for (i, feed) in feeds.iter().enumerate() {
+        for (side, token) in [(Side::Up, &feed.config.token_up), (Side::Down, &feed.config.token_down)] {
+            fetch_seed(&feed.config, i, side, token, false, &seed_tx);
+        }
+    }
+
+    // Kan næste price_change bygge videre på den kendte bog? Pr. token
+    let mut chains: HashMap<String, Chain> = HashMap::new();
+    let ack_deadline = Instant::now() + conn_config.subscribe_timeout;
+
+    // Sidst sete sekvensnummer pr. asset
+    let mut last_seq: HashMap<String, u64> = HashMap::new();
+
+    // Periodisk resync - første gang efter én periode, ikke straks
+    let resync_period = conn_config
+        .periodic_resync_secs
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let period = resync_period.unwrap_or(Duration::from_secs(3600));
+    let mut resync_timer = tokio::time::interval_at(Instant::now() + period, period);
+
+    // Keepalive - en halvåben forbindelse giver hverken data eller fejl
+    let ping_period = conn_config.ping_interval.filter(|d| !d.is_zero());
+    let ping_every = ping_period.unwrap_or(Duration::from_secs(3600));
+    let mut ping_timer = tokio::time::interval_at(Instant::now() + ping_every, ping_every);
+    let mut heard_since_ping = true;
+    let mut missed_pings = 0u32;
+
+    // Heartbeat - viser at processen lever når bogen er stille
+    let heartbeat_period = conn_config.heartbeat_interval.filter(|d| !d.is_zero());
+    let heartbeat_every = heartbeat_period.unwrap_or(Duration::from_secs(3600));
+    let mut heartbeat_timer = tokio::time::interval_at(Instant::now() + heartbeat_every, heartbeat_every);
+
+    // Coalescing - ændringer samles i `pending` og udsendes samlet på hvert tick
+    let coalesce_every = feeds.iter().filter_map(|f| coalesce_period(&f.config)).min();
+    let coalesce_tick = coalesce_every.unwrap_or(Duration::from_secs(3600));
+    let mut coalesce_timer = tokio::time::interval_at(Instant::now() + coalesce_tick, coalesce_tick);
+
+    // Event loop
+    let end = loop {
+        let next_emit_at = conns.iter().filter_map(FeedConn::emit_deadline).min();
+        let next_stale_at = conns
+            .iter()
+            .zip(feeds)
+            .filter_map(|(c, f)| c.stale_deadline(&f.config))
+            .min();
+        let next_empty = conns
+            .iter()
+            .zip(feeds)
+            .enumerate()
+            .filter_map(|(i, (c, f))| c.empty_deadline(&f.config).map(|(at, side)| (at, i, side)))
+            .min_by_key(|(at, _, _)| *at);
+        let all_live = conns.iter().all(FeedConn::live);
+        let far = Instant::now() + Duration::from_secs(3600);
+
+        tokio::select! {
+            // Shutdown signal
+            _ = &mut *shutdown_rx => {
+                break ConnectionEnd::Shutdown;
+            }
+
+            // Udsend samlede ændringer når intervallet er gået
+            _ = tokio::time::sleep_until(next_emit_at.unwrap_or(far)), if next_emit_at.is_some() => {
+                let now = Instant::now();
+                for (conn, feed) in conns.iter_mut().zip(feeds) {
+                    if next_emit(conn.last_emit, conn.min_interval).is_some_and(|at| at > now) {
+                        continue;
+                    }
+                    if let Some(change) = conn.pending.take() {
+                        conn.last_emit = Some(now);
+                        let _ = feed.shared.update_tx.send(change);
+                    }
+                }
+            }
+
+            // Én samlet ændring pr. interval for markeder med ændringer siden sidste tick
+            _ = coalesce_timer.tick(), if coalesce_every.is_some() => {
+                for (conn, feed) in conns.iter_mut().zip(feeds) {
+                    if let Some(change) = conn.pending.take().filter(|_| conn.coalesce) {
+                        let _ = feed.shared.update_tx.send(change);
+                    }
+                }
+            }
+
+            // En subscribe der fejler stille giver en tom bog for evigt - genforbind
+            _ = tokio::time::sleep_until(ack_deadline), if !all_live => {
+                let mut missing = Vec::new();
+                for (conn, feed) in conns.iter().zip(feeds) {
+                    if !conn.up_seen {
+                        missing.push(feed.config.labels.up.clone());
+                    }
+                    if !conn.down_seen {
+                        missing.push(feed.config.labels.down.clone());
+                    }
+                }
+                let missing = missing.join(" og ");
+                break lost(&conns, format!("subscribe ikke bekræftet for {}", missing));
+            }
+
+            // Ingen ændringer længe - meld det, og genforbind hvis ønsket
+            _ = tokio::time::sleep_until(next_stale_at.unwrap_or(far)), if next_stale_at.is_some() => {
+                let now = Instant::now();
+                let mut reconnect = None;
+                for (conn, feed) in conns.iter_mut().zip(feeds) {
+                    if conn.stale_deadline(&feed.config).is_none_or(|at| at > now) {
+                        continue;
+                    }
+                    let max = feed.config.max_staleness.unwrap_or_default();
+                    warn!(market = %feed.config.token_up, ?max, "ingen updates - bogen er forældet");
+                    if feed.config.reconnect_on_stale {
+                        reconnect = Some(format!("ingen updates i {:?}", max));
+                    }
+                    conn.stale = true;
+                    feed.shared.set_status(ConnectionStatus::Stale);
+                }
+                if let Some(reason) = reconnect {
+                    break lost(&conns, reason);
+                }
+            }
+
+            // Et ben hvis bog er tømt og forblevet tom - hent et frisk snapshot for netop det token
+            _ = tokio::time::sleep_until(next_empty.map_or(far, |(at, _, _)| at)), if next_empty.is_some() => {
+                if let Some((_, i, side)) = next_empty {
+                    let feed = &feeds[i];
+                    let token = match side {
+                        Side::Up => &feed.config.token_up,
+                        Side::Down => &feed.config.token_down,
+                    };
+                    warn!(%token, after = ?feed.config.empty_book_resync, "tom bog - resubscribe");
+                    record_resync(&feed.shared);
+                    resubscribe(&mut write, token).await;
+                    // Prøv igen efter samme tid hvis snapshottet også er tomt
+                    *conns[i].empty_since_mut(side) = Some(Instant::now());
+                }
+            }
+
+            // Ping - intet hørt i to intervaller betyder at forbindelsen er død
+            _ = ping_timer.tick(), if ping_period.is_some() => {
+                if heard_since_ping {
+                    missed_pings = 0;
+                } else {
+                    missed_pings += 1;
+                    if missed_pings >= 2 {
+                        break lost(&conns, format!("intet svar på ping i {:?}", ping_every * 2));
+                    }
+                }
+                heard_since_ping = false;
+                if write.send(Message::Ping(Vec::new())).await.is_err() {
+                    break lost(&conns, "ping kunne ikke sendes".to_string());
+                }
+            }
+
+            _ = heartbeat_timer.tick(), if heartbeat_period.is_some() => {
+                let now_ms = clock.now_ms();
+                for (conn, feed) in conns.iter_mut().zip(feeds) {
+                    let status = *feed.shared.status.lock().unwrap();
+                    let last = feed.shared.latest.load().last_update_ms;
+                    let age_ms = (last > 0).then(|| now_ms - last);
+                    let messages = std::mem::take(&mut conn.heartbeat_messages);
+                    info!(market = %feed.config.token_up, ?status, ?age_ms, messages, "heartbeat");
+                    let _ = feed.shared.event_tx.send(FeedEvent::Heartbeat { status, age_ms, messages });
+                }
+            }
+
+            // REST-snapshot - en live bog er altid nyere, så den overskrives kun ved resync
+            Some(seed) = seed_rx.recv() => {
+                let book = match seed.book {
+                    Ok(book) => book,
+                    Err(e) => {
+                        let config = &feeds[seed.market].config;
+                        let token = match seed.side {
+                            Side::Up => &config.token_up,
+                            Side::Down => &config.token_down,
+                        };
+                        warn!(%token, error = %e, "REST-snapshot fejlede");
+                        // En brudt kæde venter stadig på et snapshot - bed WS om et i stedet
+                        if seed.resync {
+                            resubscribe(&mut write, token).await;
+                        }
+                        continue;
+                    }
+                };
+                let conn = &mut conns[seed.market];
+                let live = match seed.side {
+                    Side::Up => conn.up_ready,
+                    Side::Down => conn.down_ready,
+                };
+                if seed.resync || !live {
+                    let _ = check_chain(&book, &mut chains);
+                    apply_events(&feeds[seed.market], conn, &[book]).await;
+                }
+            }
+
+            // Manuel resync fra handle: byg bøgerne forfra fra nye snapshots
+            Some(request) = resync_rx.recv() => {
+                let feed = &feeds[request.market];
+                info!(market = %feed.config.token_up, "manuel resync");
+                // Handelshistorikken kommer fra en anden kanal og beholdes
+                let books = &mut feed.shared.state.write().await.books;
+                (books.up, books.down) = Default::default();
+                for token in [&feed.config.token_up, &feed.config.token_down] {
+                    last_seq.remove(token.as_str());
+                    // Deltas før det nye snapshot droppes stille i stedet for at udløse endnu en resync
+                    let chain = Chain {
+                        awaiting_snapshot: true,
+                        ..Default::default()
+                    };
+                    chains.insert(token.clone(), chain);
+                    resubscribe(&mut write, token).await;
+                }
+                record_resync(&feed.shared);
+                let _ = request.done.send(());
+            }
+
+            // Periodisk resync uden at droppe forbindelsen
+            _ = resync_timer.tick(), if resync_period.is_some() => {
+                info!(assets = all_assets.len(), "periodisk resync");
+                last_seq.clear();
+                for feed in feeds {
+                    record_resync(&feed.shared);
+                }
+                for asset in &all_assets {
+                    resubscribe(&mut write, asset).await;
+                }
+            }
+
+            // WebSocket message
+            msg = read.next() => {
+                let txt = match msg {
+                    None => break lost(&conns, "stream lukket".to_string()),
+                    Some(Err(e)) => break lost(&conns, e.to_string()),
+                    Some(Ok(msg)) => {
+                        // Enhver besked (også Pong) viser at forbindelsen lever
+                        heard_since_ping = true;
+                        match msg {
+                            Message::Text(txt) => txt,
+                            Message::Ping(payload) => {
+                                let _ = write.send(Message::Pong(payload)).await;
+                                continue;
+                            }
+                            // Pæn lukning fra serveren - koden afgør hvor hårdt der bakkes af
+                            Message::Close(frame) => {
+                                let (code, reason) = frame
+                                    .map(|f| (u16::from(f.code), f.reason.into_owned()))
+                                    .unwrap_or((1005, String::new()));
+                                warn!(code, %reason, "serveren sendte Close");
+                                break ConnectionEnd::Lost(Error::ServerClosed { code, reason });
+                            }
+                            Message::Binary(data) => {
+                                debug!(len = data.len(), "ignorerer binær frame");
+                                continue;
+                            }
+                            Message::Pong(_) | Message::Frame(_) => continue,
+                        }
+                    }
+                };
+                let data = match serde_json::from_str::<serde_json::Value>(&txt) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        warn!(error = %e, len = txt.len(), "ulæselig besked");
+                        #[cfg(feature = "metrics")]
+                        metrics::record_parse_failure();
+                        // Kan ikke henføres til et marked - tæller for dem alle. Næste besked
+                        // behandles som normalt; en vedvarende spike meldes én gang
+                        let now_ms = clock.now_ms();
+                        for (feed, conn) in feeds.iter().zip(conns.iter_mut()) {
+                            let rate = {
+                                let mut stats = feed.shared.stats.lock().unwrap();
+                                stats.parse_failures.record(now_ms);
+                                stats.parse_failures.rate(now_ms)
+                            };
+                            let _ = feed.shared.event_tx.send(FeedEvent::Error(Error::Parse(e.to_string())));
+                            let Some(limit) = feed.config.parse_failure_alert_rate else { continue };
+                            if rate <= limit {
+                                conn.parse_spike = false;
+                            } else if !conn.parse_spike {
+                                conn.parse_spike = true;
+                                let per_minute = (rate * 60.0).round() as u32;
+                                warn!(market = %feed.config.token_up, per_minute, "mange ulæselige beskeder - ændret format?");
+                                let _ = feed.shared.event_tx.send(FeedEvent::ParseFailureSpike { per_minute });
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                // En frame kan være ét event eller et array af events (fx initiale bøger),
+                // og kan dække flere markeder
+                let mut routed: Vec<Vec<serde_json::Value>> = vec![Vec::new(); feeds.len()];
+                for event in split_events(data) {
+                    let Some(asset) = asset_id(&event) else { continue };
+                    // Hot path - felterne evalueres kun når trace er slået til
+                    trace!(%asset, event_type = event_type(&event), "besked");
+                    #[cfg(feature = "metrics")]
+                    metrics::record_message(asset);
+                    if let Some(&(i, _)) = route.get(asset) {
+                        routed[i].push(event);
+                    }
+                }
+
+                for (i, events) in routed.iter().enumerate() {
+                    if events.is_empty() {
+                        continue;
+                    }
+                    let (feed, conn) = (&feeds[i], &mut conns[i]);
+                    conn.heartbeat_messages += events.len() as u64;
+
+                    // Første bogbesked pr. token bekræfter subscription
+                    if !conn.live() {
+                        for event in events.iter().filter(|e| event_type(e) != TRADE_CHANNEL) {
+                            match asset_id(event).and_then(|a| route.get(a)) {
+                                Some((_, Side::Up)) => conn.up_seen = true,
+                                Some((_, Side::Down)) => conn.down_seen = true,
+                                None => {}
+                            }
+                        }
+                        if conn.live() {
+                            info!(market = %feed.config.token_up, "bøger live");
+                            conn.last_change = Instant::now();
+                            feed.shared.set_status(ConnectionStatus::BooksLive);
+                        }
+                    }
+
+                    // Hul i sekvensen = missede updates - hent et frisk snapshot for asset'et
+                    if feed.config.resync_on_gap {
+                        for event in events {
+                            if let Some((asset, gap)) = check_sequence(event, &mut last_seq) {
+                                warn!(%asset, gap, "sekvenshul - resync");
+                                last_seq.remove(&asset);
+                                record_resync(&feed.shared);
+                                resubscribe(&mut write, &asset).await;
+                            }
+                        }
+                    }
+
+                    // Deltas der ikke hænger sammen med bogen anvendes ikke - hent en frisk
+                    // bog i stedet (REST hvis muligt, ellers resubscribe)
+                    let mut chained = Vec::with_capacity(events.len());
+                    for event in events {
+                        match check_chain(event, &mut chains) {
+                            ChainCheck::Apply => chained.push(event.clone()),
+                            ChainCheck::Stale => {}
+                            ChainCheck::Broken(reason) => {
+                                let Some(&(_, side)) = asset_id(event).and_then(|a| route.get(a)) else { continue };
+                                let token = asset_id(event).unwrap_or_default().to_string();
+                                warn!(%token, %reason, "kæden brudt - resync");
+                                record_resync(&feed.shared);
+                                if feed.config.seed_client.is_some() {
+                                    fetch_seed(&feed.config, i, side, &token, true, &seed_tx);
+                                } else {
+                                    resubscribe(&mut write, &token).await;
+                                }
+                            }
+                        }
+                    }
+
+                    apply_events(feed, conn, &chained).await;
+                }
+            }
+        }
+    };
+
+    // Udsend ventende ændringer så de ikke går tabt med forbindelsen
+    for (conn, feed) in conns.iter().zip(feeds) {
+        if let Some(change) = conn.pending {
+            let _ = feed.shared.update_tx.send(change);
+        }
+    }
+
+    // Pæn nedlukning: afmeld alle tokens og luk med en Close-frame
+    if let ConnectionEnd::Shutdown = end {
+        let close = async {
+            for batch in all_assets.chunks(per_frame) {
+                let _ = write.send(book_frame("unsubscribe", batch)).await;
+            }
+            for batch in trade_assets.chunks(per_frame) {
+                let _ = write.send(channel_frame("unsubscribe", TRADE_CHANNEL, batch)).await;
+            }
+            let _ = write.send(Message::Close(None)).await;
+            let _ = write.close().await;
+        };
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, close).await.is_err() {
+            warn!(timeout = ?SHUTDOWN_TIMEOUT, "lukning tog for lang tid - dropper forbindelsen");
+        }
+    }
+
+    Ok(end)
 }
 
-/// Signal der udsendes ved state-ændring
-#[derive(Debug, Clone)]
-pub struct StateUpdated;
+/// Et REST-snapshot på vej ind i event loopet
+struct Seed {
+    market: usize,
+    side: Side,
+    book: Result<serde_json::Value, reqwest::Error>,
+    /// Resync efter brudt kæde - erstatter også en live bog
+    resync: bool,
+}
 
-/// Input til orderbook data layer
-pub struct OrderbookConfig {
-    pub token_up: String,
-    pub token_down: String,
+/// Hent REST-snapshot for `token` i baggrunden, hvis markedet har en `seed_client`
+fn fetch_seed(
+    config: &OrderbookConfig,
+    market: usize,
+    side: Side,
+    token: &str,
+    resync: bool,
+    seed_tx: &mpsc::Sender<Seed>,
+) {
+    let Some(client) = config.seed_client.clone() else { return };
+    let (token, seed_tx) = (token.to_string(), seed_tx.clone());
+    let book_api = config.rest_book_url.clone();
+    let timeout = config.subscribe_timeout;
+    tokio::spawn(async move {
+        let book = market::fetch_book(&client, &book_api, &token, timeout).await;
+        let _ = seed_tx.send(Seed { market, side, book, resync }).await;
+    });
 }
 
-/// Handle til at interagere med orderbook data layer
-pub struct OrderbookHandle {
-    state: Arc<RwLock<OrderbookState>>,
-    update_tx: broadcast::Sender<StateUpdated>,
-    shutdown_tx: tokio::sync::oneshot::Sender<()>,
+fn record_resync(shared: &Shared) {
+    let now_ms = shared.clock.now_ms();
+    shared.stats.lock().unwrap().resyncs.record(now_ms);
+    #[cfg(feature = "metrics")]
+    metrics::record_resync();
 }
 
-impl OrderbookHandle {
-    /// Læs nuværende orderbook state
-    pub async fn get_current_state(&self) -> OrderbookState {
-        self.state.read().await.clone()
+/// Hvad vi ved om et tokens bog på denne forbindelse
+#[derive(Default)]
+struct Chain {
+    /// Der er et snapshot at bygge deltas på
+    has_snapshot: bool,
+    /// Resync er bestilt - yderligere deltas droppes stille indtil snapshottet kommer
+    awaiting_snapshot: bool,
+    /// Tidsstempel (ms) for seneste anvendte besked
+    last_ts: i64,
+    /// Hash for seneste anvendte besked
+    last_hash: Option<String>,
+}
+
+/// Hvordan et event passer til den kendte bog
+enum ChainCheck {
+    Apply,
+    /// Ældre end eller identisk med bogen (samme hash) - allerede indeholdt, springes over
+    Stale,
+    /// Kan ikke bygge på bogen - tokenet afventer et nyt snapshot
+    Broken(String),
+}
+
+/// Tjek et event mod tokenets kæde og registrér det hvis det anvendes
+fn check_chain(event: &serde_json::Value, chains: &mut HashMap<String, Chain>) -> ChainCheck {
+    let Some(asset) = asset_id(event) else { return ChainCheck::Apply };
+    // Handler hører ikke til bogens kæde
+    if event_type(event) == TRADE_CHANNEL {
+        return ChainCheck::Apply;
     }
+    let ts = server_ts(event);
+    let hash = event.get("hash").and_then(|v| v.as_str()).map(str::to_string);
+    let chain = chains.entry(asset.to_string()).or_default();
 
-    /// Subscribe til state updates
-    pub fn subscribe_updates(&self) -> broadcast::Receiver<StateUpdated> {
-        self.update_tx.subscribe()
+    if event_type(event) == "price_change" {
+        if !chain.has_snapshot {
+            if chain.awaiting_snapshot {
+                return ChainCheck::Stale;
+            }
+            chain.awaiting_snapshot = true;
+            return ChainCheck::Broken("delta uden kendt snapshot".to_string());
+        }
+        if ts.is_some_and(|ts| ts < chain.last_ts) || (hash.is_some() && hash == chain.last_hash) {
+            return ChainCheck::Stale;
+        }
+    } else {
+        chain.has_snapshot = true;
+        chain.awaiting_snapshot = false;
     }
 
-    /// Stop orderbook data layer
-    pub fn shutdown(self) {
-        let _ = self.shutdown_tx.send(());
+    if let Some(ts) = ts {
+        chain.last_ts = chain.last_ts.max(ts);
     }
+    if hash.is_some() {
+        chain.last_hash = hash;
+    }
+    ChainCheck::Apply
 }
 
-/// Start orderbook data layer - returnerer handle til interaktion
-pub fn spawn(config: OrderbookConfig) -> OrderbookHandle {
-    let state = Arc::new(RwLock::new(OrderbookState::default()));
-    let (update_tx, _) = broadcast::channel(64);
-    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+/// Serverens timestamp (ms) - som tal eller streng
+fn server_ts(event: &serde_json::Value) -> Option<i64> {
+    event
+        .get("timestamp")
+        .and_then(|v| v.as_i64().or_else(|| v.as_str()?.parse().ok()))
+}
 
-    let state_clone = state.clone();
-    let update_tx_clone = update_tx.clone();
+/// Beskedtype - `book` (snapshot) når feltet mangler
+fn event_type(data: &serde_json::Value) -> &str {
+    data.get("event_type")
+        .or_else(|| data.get("type"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("book")
+}
 
-    tokio::spawn(async move {
-        run_websocket_loop(config, state_clone, update_tx_clone, shutdown_rx).await;
-    });
+/// Anvend et markeds events fra én frame og udsend signaler
+async fn apply_events(feed: &Feed, conn: &mut FeedConn, events: &[serde_json::Value]) {
+    let (config, shared) = (&feed.config, &feed.shared);
 
-    OrderbookHandle {
-        state,
-        update_tx,
-        shutdown_tx,
+    // Anvend hele framen under én write-lock, så læsere aldrig ser en halv batch
+    let now_ms = shared.clock.now_ms();
+    let mut updated = Vec::new();
+    let (arb, change, snapshot, status) = {
+        let mut inner = shared.state.write().await;
+        let Inner { top, books } = &mut *inner;
+        let before = top.clone();
+        let mut latencies = Vec::new();
+        for event in events {
+            // Enhver besked viser at benet lever - også en der ikke ændrer top-of-book
+            match asset_id(event) {
+                Some(asset) if asset == config.token_up => top.up_last_message_ms = now_ms,
+                Some(asset) if asset == config.token_down => top.down_last_message_ms = now_ms,
+                _ => {}
+            }
+            if let Some(side) = process_message(event, config, top, books, &shared.event_tx, now_ms) {
+                updated.push(side);
+                if let Some(ts) = server_ts(event) {
+                    latencies.push(now_ms - ts);
+                }
+            }
+        }
+        if !latencies.is_empty() {
+            let mut stats = shared.stats.lock().unwrap();
+            for latency in latencies {
+                stats.latency.record(latency);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        if !updated.is_empty() {
+            metrics::observe_state(&config.token_up, top);
+        }
+        // Tomme ben blandt dem framen rørte - ikke-tomme nulstiller vagten
+        for side in [Side::Up, Side::Down] {
+            let token = match side {
+                Side::Up => &config.token_up,
+                Side::Down => &config.token_down,
+            };
+            if !events.iter().any(|e| asset_id(e) == Some(token.as_str())) {
+                continue;
+            }
+            let since = conn.empty_since_mut(side);
+            if !books.get(side).is_empty() {
+                *since = None;
+            } else if since.is_none() {
+                *since = Some(Instant::now());
+            }
+        }
+        // Publicér under write-locken, så `latest()` aldrig er ældre end en læst `get_current_state`
+        let snapshot = Arc::new(top.clone());
+        shared.latest.store(snapshot.clone());
+        let status = *shared.status.lock().unwrap();
+        let arb = config.circuit_breaker.detect(top, status, now_ms);
+        (arb, StateUpdated::between(&before, top), snapshot, status)
+    };
+    if updated.is_empty() {
+        return;
+    }
+    conn.last_change = Instant::now();
+    if conn.stale {
+        conn.stale = false;
+        shared.set_status(ConnectionStatus::BooksLive);
     }
-}
 
-async fn run_websocket_loop(
-    config: OrderbookConfig,
-    state: Arc<RwLock<OrderbookState>>,
-    update_tx: broadcast::Sender<StateUpdated>,
-    mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
-) {
-    // Forbind til WebSocket
-    let (ws, _) = match connect_async(WS_URL).await {
-        Ok(conn) => conn,
-        Err(e) => {
-            eprintln!("[orderbook] WS connect error: {}", e);
-            return;
+    if let Some(opportunity) = arb.filter(|_| arb != conn.last_arb) {
+        #[cfg(feature = "metrics")]
+        metrics::record_arb(&config.token_up);
+        shared.signal_arb(opportunity, &snapshot, status, now_ms);
+    }
+    conn.last_arb = arb;
+    if let Some(opportunity) = &arb {
+        shared.notify_watchers(opportunity);
+    }
+
+    // Uændret top-of-book (fx kun en dyb ændring eller et identisk snapshot) vækker ingen
+    if !change.is_empty() {
+        shared.deliver_reliable().await;
+
+        // Ét signal pr. frame - eller vent hvis vi er inden for intervallet eller samler
+        let change = conn.pending.take().map_or(change, |pending| pending.merge(change));
+        if conn.coalesce {
+            conn.pending = Some(change);
+        } else if next_emit(conn.last_emit, conn.min_interval).is_none_or(|at| Instant::now() >= at) {
+            conn.last_emit = Some(Instant::now());
+            let _ = shared.update_tx.send(change);
+        } else {
+            conn.pending = Some(change);
         }
-    };
+    }
 
+    for side in updated {
+        let ready = match side {
+            Side::Up => &mut conn.up_ready,
+            Side::Down => &mut conn.down_ready,
+        };
+        if !*ready {
+            *ready = true;
+            let _ = shared.event_tx.send(FeedEvent::LegReady { side });
+        }
+    }
+}
 
-    let (mut write, mut read) = ws.split();
+/// Markedets coalesce-interval, hvis det er slået til
+fn coalesce_period(config: &OrderbookConfig) -> Option<Duration> {
+    config.coalesce_interval.filter(|d| !d.is_zero())
+}
 
-    // Subscribe til begge tokens
-    let sub_up = serde_json::json!({
-        "type": "subscribe",
-        "channel": "book",
-        "assets_ids": [&config.token_up]
-    });
-    let sub_down = serde_json::json!({
-        "type": "subscribe",
-        "channel": "book",
-        "assets_ids": [&config.token_down]
+/// Tidligste tidspunkt næste `StateUpdated` må sendes - `None` er med det samme
+fn next_emit(last_emit: Option<Instant>, min_interval: Option<Duration>) -> Option<Instant> {
+    Some(last_emit? + min_interval?)
+}
+
+/// Kanal og beskedtype for handler
+const TRADE_CHANNEL: &str = "last_trade_price";
+
+/// Subscribe/unsubscribe-frame for `book`-kanalen
+fn book_frame(kind: &str, asset_ids: &[&str]) -> Message {
+    channel_frame(kind, "book", asset_ids)
+}
+
+fn channel_frame(kind: &str, channel: &str, asset_ids: &[&str]) -> Message {
+    let frame = serde_json::json!({
+        "type": kind,
+        "channel": channel,
+        "assets_ids": asset_ids
     });
+    Message::Text(frame.to_string())
+}
 
-    if write.send(Message::Text(sub_up.to_string())).await.is_err() {
-        eprintln!("[orderbook] Fejl ved subscribe UP");
-        return;
+/// Unsubscribe + subscribe, så serveren sender et frisk snapshot for asset'et
+async fn resubscribe<W>(write: &mut W, asset_id: &str)
+where
+    W: futures_util::Sink<Message> + Unpin,
+{
+    let _ = write.send(book_frame("unsubscribe", &[asset_id])).await;
+    let _ = write.send(book_frame("subscribe", &[asset_id])).await;
+}
+
+fn asset_id(data: &serde_json::Value) -> Option<&str> {
+    data.get("asset_id")
+        .or_else(|| data.get("assetId"))
+        .or_else(|| data.get("token_id"))
+        .and_then(|v| v.as_str())
+}
+
+/// Registrér beskedens sekvensnummer og returnér (asset, antal mistede) ved et hul
+fn check_sequence(
+    data: &serde_json::Value,
+    last_seq: &mut HashMap<String, u64>,
+) -> Option<(String, u64)> {
+    let asset = asset_id(data)?;
+    let seq = data
+        .get("seq")
+        .or_else(|| data.get("sequence"))
+        .and_then(|v| v.as_u64().or_else(|| v.as_str()?.parse().ok()))?;
+
+    let prev = last_seq.insert(asset.to_string(), seq)?;
+    if seq > prev + 1 {
+        Some((asset.to_string(), seq - prev - 1))
+    } else {
+        None
     }
-    if write.send(Message::Text(sub_down.to_string())).await.is_err() {
-        eprintln!("[orderbook] Fejl ved subscribe DOWN");
-        return;
+}
+
+/// Niveau-bøger for de to ben
+#[derive(Default)]
+struct Books {
+    up: LevelBook,
+    down: LevelBook,
+    /// Handler inden for `trade_window`, ældste først
+    up_trades: VecDeque<Trade>,
+    down_trades: VecDeque<Trade>,
+}
+
+/// Én handel fra `last_trade_price`
+struct Trade {
+    ts_ms: i64,
+    price: Decimal,
+    size: Decimal,
+}
+
+impl Books {
+    fn get(&self, side: Side) -> &LevelBook {
+        match side {
+            Side::Up => &self.up,
+            Side::Down => &self.down,
+        }
     }
 
+    fn get_mut(&mut self, side: Side) -> &mut LevelBook {
+        match side {
+            Side::Up => &mut self.up,
+            Side::Down => &mut self.down,
+        }
+    }
 
-    // Event loop
-    loop {
-        tokio::select! {
-            // Shutdown signal
-            _ = &mut shutdown_rx => {
-                break;
-            }
+    fn trades(&self, side: Side) -> &VecDeque<Trade> {
+        match side {
+            Side::Up => &self.up_trades,
+            Side::Down => &self.down_trades,
+        }
+    }
 
-            // WebSocket message
-            msg = read.next() => {
-                let Some(msg) = msg else {
-                    break;
-                };
+    /// Gem en handel og glem dem der er ældre end `window`
+    fn record_trade(&mut self, side: Side, trade: Trade, window: Duration) {
+        let trades = match side {
+            Side::Up => &mut self.up_trades,
+            Side::Down => &mut self.down_trades,
+        };
+        let cutoff = trade.ts_ms - window.as_millis() as i64;
+        trades.push_back(trade);
+        while trades.front().is_some_and(|t| t.ts_ms < cutoff) {
+            trades.pop_front();
+        }
+    }
+}
+
+/// Del en frame op i events pr. asset.
+///
+/// En frame kan være ét event, et array af events (fx initiale bøger), eller en
+/// `price_change` hvor hver ændring bærer sit eget `asset_id` i `price_changes`.
+fn split_events(data: serde_json::Value) -> Vec<serde_json::Value> {
+    let items = match data {
+        serde_json::Value::Array(items) => items,
+        single => vec![single],
+    };
 
-                let Ok(Message::Text(txt)) = msg else { continue };
+    let mut events = Vec::with_capacity(items.len());
+    for item in items {
+        let Some(changes) = item.get("price_changes").and_then(|v| v.as_array()) else {
+            events.push(item);
+            continue;
+        };
 
-                if let Some(updated) = process_message(&txt, &config, &state).await {
-                    if updated {
-                        // Signal state ændring
-                        let _ = update_tx.send(StateUpdated);
-                    }
+        // Gruppér ændringerne pr. asset, i den rækkefølge de optræder
+        let mut grouped: Vec<(String, Vec<serde_json::Value>)> = Vec::new();
+        for change in changes {
+            let Some(asset) = asset_id(change).or_else(|| asset_id(&item)) else {
+                continue;
+            };
+            match grouped.iter_mut().find(|(a, _)| a == asset) {
+                Some((_, list)) => list.push(change.clone()),
+                None => grouped.push((asset.to_string(), vec![change.clone()])),
+            }
+        }
+        for (asset, changes) in grouped {
+            let mut event = serde_json::json!({
+                "event_type": "price_change",
+                "asset_id": asset,
+                "changes": changes,
+            });
+            for key in ["timestamp", "seq", "sequence"] {
+                if let Some(v) = item.get(key) {
+                    event[key] = v.clone();
                 }
             }
+            events.push(event);
         }
     }
-
+    events
 }
 
-/// Processér en WebSocket besked og opdater state
-/// Returnerer Some(true) hvis state blev opdateret, Some(false) hvis ikke, None ved parse fejl
-async fn process_message(
-    txt: &str,
+/// Processér ét book-event og opdater state
+/// Returnerer benet hvis state blev opdateret, None hvis ikke
+fn process_message(
+    data: &serde_json::Value,
     config: &OrderbookConfig,
-    state: &Arc<RwLock<OrderbookState>>,
-) -> Option<bool> {
-    let data: serde_json::Value = serde_json::from_str(txt).ok()?;
-
+    s: &mut OrderbookState,
+    books: &mut Books,
+    event_tx: &broadcast::Sender<FeedEvent>,
+    now_ms: i64,
+) -> Option<Side> {
     // Find asset ID
-    let asset_id = data
-        .get("asset_id")
-        .or_else(|| data.get("assetId"))
-        .or_else(|| data.get("token_id"))
-        .and_then(|v| v.as_str())?;
+    let asset_id = asset_id(data)?;
 
     let is_up = asset_id == config.token_up;
     let is_down = asset_id == config.token_down;
     if !is_up && !is_down {
-        return Some(false);
-    }
-
-    // Parse bids og asks
-    let bids: Vec<serde_json::Value> = data
-        .get("bids")
-        .and_then(|v| v.as_array())
-        .cloned()
-        .unwrap_or_default();
-    let asks: Vec<serde_json::Value> = data
-        .get("asks")
-        .and_then(|v| v.as_array())
-        .cloned()
-        .unwrap_or_default();
-
-    // Best bid/ask - kun hvis der er data
-    let best_bid = bids.last().and_then(|v| {
-        let price = v.get("price")?.as_str()?;
-        let size = v.get("size")?.as_str()?;
-        Some((price.to_string(), size.to_string()))
-    });
-    let best_ask = asks.last().and_then(|v| {
-        let price = v.get("price")?.as_str()?;
-        let size = v.get("size")?.as_str()?;
-        Some((price.to_string(), size.to_string()))
-    });
+        return None;
+    }
+    let side = if is_up { Side::Up } else { Side::Down };
+
+    // Frakoblet ben ignoreres indtil cooldown er udløbet
+    let health = s.health_mut(side);
+    if let LegHealth::Disabled { until_ms } = *health {
+        if now_ms < until_ms {
+            return None;
+        }
+        *health = LegHealth::Healthy;
+        let _ = event_tx.send(FeedEvent::LegEnabled { side });
+    }
 
-    // Hvis ingen data, behold tidligere state
-    if best_bid.is_none() && best_ask.is_none() {
-        return Some(false);
+    // Snapshot erstatter bogen, price_change anvender deltas - på en kopi,
+    // så dårlig data ikke ødelægger den kendte bog
+    let event_type = event_type(data);
+    if event_type == TRADE_CHANNEL {
+        if config.track_trades {
+            record_trade(data, config, s, books, side, now_ms);
+        }
+        return None;
     }
+    let mut book = books.get_mut(side).clone();
+    let applied = match event_type {
+        "book" => book.apply_snapshot(data),
+        "price_change" => data
+            .get("changes")
+            .and_then(|v| v.as_array())
+            .map(|changes| changes.iter().try_for_each(|c| book.apply_change(c)))
+            .unwrap_or(Ok(())),
+        _ => return None,
+    };
 
-    let now_ms = chrono::Utc::now().timestamp_millis();
+    // Ulæselige, ugyldige eller krydsede niveauer tæller som dårlig data
+    if applied.is_err() || book.is_crossed() {
+        record_bad_message(s, side, config, now_ms, event_tx);
+        return None;
+    }
 
-    // Opdater state - kun felter med ny data, behold resten
-    {
-        let mut s = state.write().await;
-        
-        if is_up {
-            // Opdater UP direkte
-            if let Some((price, size)) = &best_bid {
-                s.up_bid_price = price.clone();
-                s.up_bid_size = size.clone();
-                // DOWN ask = 1 - UP bid
-                if let Ok(p) = price.parse::<f64>() {
-                    s.down_ask_price = format!("{:.2}", 1.0 - p);
-                    s.down_ask_size = size.clone();
-                }
-            }
-            if let Some((price, size)) = &best_ask {
-                s.up_ask_price = price.clone();
-                s.up_ask_size = size.clone();
-                // DOWN bid = 1 - UP ask
-                if let Ok(p) = price.parse::<f64>() {
-                    s.down_bid_price = format!("{:.2}", 1.0 - p);
-                    s.down_bid_size = size.clone();
+    // Rigtige og afledte priser med samme antal decimaler, så felterne er sammenlignelige
+    let best_bid = book.best_bid().map(|l| (at_tick_scale(l.price, config.tick_size), l.size));
+    let best_ask = book.best_ask().map(|l| (at_tick_scale(l.price, config.tick_size), l.size));
+    if !book.is_empty() {
+        match side {
+            Side::Up => s.up_last_nonempty_ms = now_ms,
+            Side::Down => s.down_last_nonempty_ms = now_ms,
+        }
+    }
+    *books.get_mut(side) = book;
+
+    // Et snapshot uden niveauer beholder tidligere state
+    if event_type == "book" && best_bid.is_none() && best_ask.is_none() {
+        return None;
+    }
+
+    // Opdater state fra bogen - en side som deltas har tømt ryddes, og det
+    // komplement der var afledt af den erstattes af det modsatte bens egen pris.
+    // Det modsatte ben afledes kun som 1 - p (eksakt i Decimal) hvis det er slået til,
+    // og aldrig hen over benets egen bog - en låst bog (lig med) er tilladt.
+    let other = side.other();
+    let mut crossed = false;
+    match best_bid {
+        Some((price, size)) => {
+            s.set_bid(side, price, size, false);
+            // Modsat ask = 1 - bid
+            if config.derive_complement {
+                let ask = complement(price, config, true);
+                if s.real_bid(other).is_some_and(|bid| ask < bid) {
+                    crossed = true;
+                } else {
+                    s.set_ask(other, ask, size, true);
                 }
             }
-        } else {
-            // Opdater DOWN direkte
-            if let Some((price, size)) = &best_bid {
-                s.down_bid_price = price.clone();
-                s.down_bid_size = size.clone();
-                // UP ask = 1 - DOWN bid
-                if let Ok(p) = price.parse::<f64>() {
-                    s.up_ask_price = format!("{:.2}", 1.0 - p);
-                    s.up_ask_size = size.clone();
+        }
+        None => {
+            s.clear_bid(side);
+            // Det afledte komplement falder tilbage til det modsatte bens egen bog
+            if config.derive_complement && s.real_ask(other).is_none() {
+                match books.get(other).best_ask() {
+                    Some(l) => s.set_ask(other, at_tick_scale(l.price, config.tick_size), l.size, false),
+                    None => s.clear_ask(other),
+                }
+            }
+        }
+    }
+    match best_ask {
+        Some((price, size)) => {
+            s.set_ask(side, price, size, false);
+            // Modsat bid = 1 - ask
+            if config.derive_complement {
+                let bid = complement(price, config, false);
+                if s.real_ask(other).is_some_and(|ask| bid > ask) {
+                    crossed = true;
+                } else {
+                    s.set_bid(other, bid, size, true);
                 }
             }
-            if let Some((price, size)) = &best_ask {
-                s.down_ask_price = price.clone();
-                s.down_ask_size = size.clone();
-                // UP bid = 1 - DOWN ask
-                if let Ok(p) = price.parse::<f64>() {
-                    s.up_bid_price = format!("{:.2}", 1.0 - p);
-                    s.up_bid_size = size.clone();
+        }
+        None => {
+            s.clear_ask(side);
+            if config.derive_complement && s.real_bid(other).is_none() {
+                match books.get(other).best_bid() {
+                    Some(l) => s.set_bid(other, at_tick_scale(l.price, config.tick_size), l.size, false),
+                    None => s.clear_bid(other),
                 }
             }
         }
-        
-        s.last_update_ms = now_ms;
     }
+    if config.derive_complement {
+        s.complement_crossed = crossed;
+    }
+
+    *s.health_mut(side) = LegHealth::Healthy;
+    s.up_wide_spread = is_wide(s.up_spread(), config.max_spread);
+    s.down_wide_spread = is_wide(s.down_spread(), config.max_spread);
+    s.last_update_ms = now_ms;
+
+    Some(side)
+}
+
+/// Gem en `last_trade_price`-besked som benets seneste handel - ulæselige ignoreres
+fn record_trade(
+    data: &serde_json::Value,
+    config: &OrderbookConfig,
+    s: &mut OrderbookState,
+    books: &mut Books,
+    side: Side,
+    now_ms: i64,
+) {
+    let field = |key: &str| -> Option<Decimal> {
+        let v = data.get(key)?;
+        v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string()).parse().ok()
+    };
+    match (field("price"), field("size")) {
+        (Some(price), Some(size)) if price >= Decimal::ZERO && price <= Decimal::ONE => {
+            s.set_last_trade(side, price, size);
+            books.record_trade(side, Trade { ts_ms: now_ms, price, size }, config.trade_window);
+        }
+        _ => debug!(?side, "ulæselig handel ignoreret"),
+    }
+}
+
+/// Komplementet `1 - p` afrundet til tick efter `complement_rounding` og holdt inden for [0, 1]
+fn complement(price: Decimal, config: &OrderbookConfig, is_ask: bool) -> Decimal {
+    let strategy = config.complement_rounding.strategy(is_ask);
+    let derived = round_to_tick_with(Decimal::ONE - price, config.tick_size, strategy).clamp(Decimal::ZERO, Decimal::ONE);
+    at_tick_scale(derived, config.tick_size)
+}
+
+/// Tæl en dårlig besked og slå benet fra når tærsklen nås
+fn record_bad_message(
+    s: &mut OrderbookState,
+    side: Side,
+    config: &OrderbookConfig,
+    now_ms: i64,
+    event_tx: &broadcast::Sender<FeedEvent>,
+) {
+    let health = s.health_mut(side);
+    let consecutive_bad = match *health {
+        LegHealth::Suspect { consecutive_bad } => consecutive_bad + 1,
+        _ => 1,
+    };
 
-    Some(true)
+    match config.max_bad_messages {
+        Some(max) if consecutive_bad >= max => {
+            let until_ms = now_ms + config.leg_cooldown.as_millis() as i64;
+            *health = LegHealth::Disabled { until_ms };
+            warn!(leg = %config.labels.get(side), consecutive_bad, until_ms, "ben slået fra efter dårlige beskeder");
+            let _ = event_tx.send(FeedEvent::LegDisabled { side });
+        }
+        _ => *health = LegHealth::Suspect { consecutive_bad },
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn config() -> OrderbookConfig {
+        OrderbookConfig::builder().token_up("up").token_down("down").build().unwrap()
+    }
+
+    /// `process_message` med egen state og bøger
+    struct Fixture {
+        config: OrderbookConfig,
+        state: OrderbookState,
+        books: Books,
+        event_tx: broadcast::Sender<FeedEvent>,
+        now_ms: i64,
+    }
+
+    impl Fixture {
+        fn new(config: OrderbookConfig) -> Self {
+            Self {
+                config,
+                state: OrderbookState::default(),
+                books: Books::default(),
+                event_tx: broadcast::channel(16).0,
+                now_ms: 1_000,
+            }
+        }
+
+        fn apply(&mut self, event: Value) -> Option<Side> {
+            process_message(&event, &self.config, &mut self.state, &mut self.books, &self.event_tx, self.now_ms)
+        }
+    }
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<Value> {
+        levels.iter().map(|(price, size)| json!({ "price": price, "size": size })).collect()
+    }
+
+    fn book(asset: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> Value {
+        json!({ "event_type": "book", "asset_id": asset, "bids": levels(bids), "asks": levels(asks) })
+    }
+
+    fn change(asset: &str, side: &str, price: &str, size: &str) -> Value {
+        json!({
+            "event_type": "price_change",
+            "asset_id": asset,
+            "changes": [{ "side": side, "price": price, "size": size }],
+        })
+    }
+
+    #[test]
+    fn delta_emptying_a_side_clears_it_and_its_complement() {
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            ..config()
+        });
+        f.apply(book("up", &[("0.40", "10")], &[("0.45", "5")]));
+        assert_eq!(f.state.down_ask_price, Some(dec("0.60")));
+
+        let before = f.state.clone();
+        assert_eq!(f.apply(change("up", "BUY", "0.40", "0")), Some(Side::Up));
+        assert_eq!((f.state.up_bid_price, f.state.up_bid_size), (None, None));
+        // Den afledte DOWN ask kom fra UP bid og forsvinder med den
+        assert_eq!((f.state.down_ask_price, f.state.down_ask_size), (None, None));
+        assert_eq!(f.state.up_ask_price, Some(dec("0.45")));
+
+        let change = StateUpdated::between(&before, &f.state);
+        assert!(change.up.bid && !change.up.ask);
+        assert!(change.down.ask && !change.down.bid);
+    }
+
+    #[test]
+    fn emptied_side_restores_the_other_legs_own_price() {
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            ..config()
+        });
+        f.apply(book("down", &[("0.50", "10")], &[("0.58", "10")]));
+        f.apply(book("up", &[("0.40", "10")], &[("0.45", "5")]));
+
+        // DOWN bid er afledt af UP ask (1 - 0.45) og har overskrevet bogens egen 0.50
+        assert_eq!(f.state.down_bid_price, Some(dec("0.55")));
+        assert!(f.state.derived.down_bid);
+
+        f.apply(change("up", "SELL", "0.45", "0"));
+        assert_eq!(f.state.up_ask_price, None);
+        assert_eq!(f.state.down_bid_price, Some(dec("0.50")));
+        assert_eq!(f.state.down_bid_size, Some(dec("10")));
+        assert!(!f.state.derived.down_bid);
+    }
+
+    #[test]
+    fn empty_snapshot_keeps_previous_state() {
+        let mut f = Fixture::new(config());
+        f.apply(book("up", &[("0.40", "10")], &[("0.45", "5")]));
+
+        assert_eq!(f.apply(book("up", &[], &[])), None);
+        assert_eq!(f.state.up_bid_price, Some(dec("0.40")));
+        assert_eq!(f.state.up_ask_price, Some(dec("0.45")));
+    }
+
+    #[test]
+    fn unthrottled_emission_is_immediate() {
+        let interval = Duration::from_millis(100);
+        let last = Instant::now();
+        assert_eq!(next_emit(None, None), None);
+        assert_eq!(next_emit(Some(last), None), None);
+        assert_eq!(next_emit(None, Some(interval)), None);
+        assert_eq!(next_emit(Some(last), Some(interval)), Some(last + interval));
+    }
+
+    #[test]
+    fn clean_market_gives_book_probabilities_and_residual() {
+        let mut f = Fixture::new(config());
+        f.apply(book("up", &[("0.38", "10")], &[("0.40", "10")]));
+        f.apply(book("down", &[("0.56", "10")], &[("0.58", "10")]));
+
+        let book_prob = |prob| Some(ImpliedProb { prob: dec(prob), source: PriceSource::Book });
+        assert_eq!(f.state.implied_prob_up(), book_prob("0.40"));
+        assert_eq!(f.state.implied_prob_down(), book_prob("0.58"));
+        assert_eq!(f.state.no_arb_residual(), Some(dec("0.02")));
+    }
+
+    #[test]
+    fn missing_side_gives_no_probability_or_residual() {
+        let mut f = Fixture::new(config());
+        f.apply(book("up", &[("0.38", "10")], &[("0.40", "10")]));
+
+        assert_eq!(f.state.implied_prob_up().map(|p| p.prob), Some(dec("0.40")));
+        assert_eq!(f.state.implied_prob_down(), None);
+        assert_eq!(f.state.no_arb_residual(), None);
+    }
+
+    #[test]
+    fn derived_ask_is_the_fallback_and_marked_as_such() {
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            ..config()
+        });
+        f.apply(book("up", &[("0.38", "10")], &[("0.40", "10")]));
+
+        let derived = ImpliedProb { prob: dec("0.62"), source: PriceSource::Derived };
+        assert_eq!(f.state.implied_prob_down(), Some(derived));
+        assert_eq!(f.state.implied_prob_up().map(|p| p.source), Some(PriceSource::Book));
+        // Et afledt ben kan ikke handles - ingen residual
+        assert_eq!(f.state.no_arb_residual(), None);
+    }
+
+    #[test]
+    fn sequence_gaps_are_tracked_per_asset() {
+        let mut last_seq = HashMap::new();
+        let msg = |asset: &str, key: &str, seq: Value| json!({ "asset_id": asset, key: seq });
+
+        assert_eq!(check_sequence(&msg("up", "seq", json!(1)), &mut last_seq), None);
+        assert_eq!(check_sequence(&msg("down", "sequence", json!("10")), &mut last_seq), None);
+        assert_eq!(check_sequence(&msg("up", "seq", json!(2)), &mut last_seq), None);
+        assert_eq!(check_sequence(&msg("down", "sequence", json!("11")), &mut last_seq), None);
+        assert_eq!(check_sequence(&msg("up", "seq", json!(5)), &mut last_seq), Some(("up".to_string(), 2)));
+        // Uden sekvensnummer spores intet
+        assert_eq!(check_sequence(&json!({ "asset_id": "up" }), &mut last_seq), None);
+    }
+
+    #[test]
+    fn a_token_shared_across_markets_is_caught() {
+        let market = |up: &str, down: &str| OrderbookConfig::builder().token_up(up).token_down(down).build().unwrap();
+        assert_eq!(check_unique_assets(&[market("a", "b"), market("c", "d")]), Ok(()));
+
+        let err = check_unique_assets(&[market("a", "b"), market("c", "d"), market("e", "b")]).unwrap_err();
+        assert_eq!(err, DuplicateAsset { asset_id: "b".to_string(), first: 0, second: 2 });
+        assert_eq!(err.to_string(), "asset b bruges af både marked #0 og #2");
+        // Også inden for ét marked
+        assert!(check_unique_assets(&[market("a", "a")]).is_err());
+        assert!(spawn_multi(vec![market("a", "b"), market("b", "c")]).is_err());
+    }
+
+    #[test]
+    fn fair_value_blends_both_legs() {
+        let mut f = Fixture::new(config());
+        f.apply(book("up", &[("0.39", "10")], &[("0.41", "10")]));
+        assert_eq!(f.state.fair_up_prob(), None);
+
+        // Benene er enige: 0.40 og 1 - 0.60
+        f.apply(book("down", &[("0.59", "10")], &[("0.61", "10")]));
+        assert_eq!(f.state.fair_up_prob(), Some(dec("0.40")));
+
+        // Uenige: UP siger 0.40, DOWN siger 1 - 0.50 = 0.50 - midt imellem
+        f.apply(book("down", &[("0.48", "10")], &[("0.52", "10")]));
+        assert_eq!(f.state.fair_up_prob(), Some(dec("0.45")));
+    }
+
+    #[test]
+    fn frames_split_into_one_event_per_asset() {
+        // Et enkelt event og et array afleveres som de er
+        assert_eq!(split_events(book("up", &[], &[])), [book("up", &[], &[])]);
+        let batch = json!([book("up", &[], &[]), change("down", "BUY", "0.50", "1")]);
+        assert_eq!(split_events(batch), [book("up", &[], &[]), change("down", "BUY", "0.50", "1")]);
+
+        // price_changes grupperes pr. asset i rækkefølge, med framens timestamp og seq
+        let frame = json!({
+            "event_type": "price_change",
+            "timestamp": "1700",
+            "seq": 4,
+            "price_changes": [
+                { "asset_id": "down", "side": "SELL", "price": "0.60", "size": "1" },
+                { "asset_id": "up", "side": "BUY", "price": "0.40", "size": "2" },
+                { "asset_id": "down", "side": "BUY", "price": "0.55", "size": "3" },
+            ],
+        });
+        let events = split_events(frame);
+        assert_eq!(events.len(), 2);
+        assert_eq!((asset_id(&events[0]), asset_id(&events[1])), (Some("down"), Some("up")));
+        assert_eq!(events[0]["changes"].as_array().unwrap().len(), 2);
+        assert_eq!((&events[1]["timestamp"], &events[1]["seq"]), (&json!("1700"), &json!(4)));
+        assert!(events.iter().all(|e| event_type(e) == "price_change"));
+    }
+
+    #[test]
+    fn event_type_defaults_to_book() {
+        assert_eq!(event_type(&json!({ "event_type": "price_change" })), "price_change");
+        assert_eq!(event_type(&json!({ "type": "last_trade_price" })), "last_trade_price");
+        assert_eq!(event_type(&json!({ "asset_id": "up", "bids": [] })), "book");
+    }
+
+    #[test]
+    fn microprice_leans_towards_the_thinner_side() {
+        let mut f = Fixture::new(config());
+        f.apply(book("up", &[("0.40", "30")], &[("0.44", "10")]));
+        f.apply(book("down", &[("0.55", "10")], &[("0.60", "15")]));
+
+        // (0.40*10 + 0.44*30) / 40 og (0.55*15 + 0.60*10) / 25
+        assert_eq!(f.state.up_microprice(), Some(dec("0.43")));
+        assert_eq!(f.state.down_microprice(), Some(dec("0.57")));
+        // Lige store sizes giver mid
+        assert_eq!(microprice(dec("0.40"), dec("5"), dec("0.44"), dec("5")), Some(dec("0.42")));
+        assert_eq!(microprice(dec("0.40"), dec("0"), dec("0.44"), dec("0")), None);
+
+        f.apply(change("up", "SELL", "0.44", "0"));
+        assert_eq!(f.state.up_microprice(), None);
+    }
+
+    #[test]
+    fn complement_of_a_bid_is_exact() {
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            ..config()
+        });
+        f.apply(book("up", &[("0.37", "12.5")], &[]));
+
+        let ask = f.state.down_ask_price.unwrap();
+        assert_eq!(ask, dec("0.63"));
+        assert_eq!(ask.to_string(), "0.63");
+        assert_eq!(f.state.down_ask_size, Some(dec("12.5")));
+        assert!(f.state.derived.down_ask);
+    }
+
+    #[test]
+    fn complements_follow_the_market_tick() {
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            tick_size: dec("0.001"),
+            ..config()
+        });
+        f.apply(book("up", &[("0.375", "10")], &[("0.5", "10")]));
+
+        // 3 decimaler: intet rundes væk, og afledte priser vises med markedets præcision
+        assert_eq!(f.state.down_ask_price.map(|p| p.to_string()), Some("0.625".to_string()));
+        assert_eq!(f.state.down_bid_price.map(|p| p.to_string()), Some("0.500".to_string()));
+
+        // Standard-tick 0.01 giver 2 decimaler
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            ..config()
+        });
+        f.apply(book("up", &[("0.37", "10")], &[("0.5", "10")]));
+        assert_eq!(f.state.down_ask_price.map(|p| p.to_string()), Some("0.63".to_string()));
+        assert_eq!(f.state.down_bid_price.map(|p| p.to_string()), Some("0.50".to_string()));
+    }
+
+    #[test]
+    fn a_crossed_complement_is_flagged_instead_of_stored() {
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            ..config()
+        });
+        f.apply(book("down", &[("0.65", "10")], &[]));
+
+        // 1 - 0.40 = 0.60 ville ligge under DOWNs eget bid på 0.65
+        f.apply(book("up", &[("0.40", "10")], &[]));
+        assert!(f.state.complement_crossed);
+        assert_eq!(f.state.down_ask_price, None);
+        assert_eq!(f.state.down_bid_price, Some(dec("0.65")));
+
+        // Låst (0.60 mod et bid på 0.60) er tilladt og rydder flaget
+        f.apply(book("down", &[("0.60", "10")], &[]));
+        f.apply(book("up", &[("0.40", "10")], &[]));
+        assert!(!f.state.complement_crossed);
+        assert_eq!(f.state.down_ask_price, Some(dec("0.60")));
+    }
+
+    #[test]
+    fn complements_are_clamped_to_the_unit_interval() {
+        let config = config();
+        assert_eq!(complement(dec("1.05"), &config, true), dec("0.00"));
+        assert_eq!(complement(dec("-0.02"), &config, false), dec("1.00"));
+        assert_eq!(complement(dec("0"), &config, true), dec("1"));
+    }
+
+    #[test]
+    fn complement_rounding_modes() {
+        let with = |rounding| OrderbookConfig {
+            complement_rounding: rounding,
+            ..config()
+        };
+        // 1 - 0.375 = 0.625 ligger midt mellem to ticks
+        let p = dec("0.375");
+        assert_eq!(complement(p, &with(ComplementRounding::Nearest), true), dec("0.62"));
+        assert_eq!(complement(p, &with(ComplementRounding::Up), false), dec("0.63"));
+        assert_eq!(complement(p, &with(ComplementRounding::Down), true), dec("0.62"));
+
+        // Konservativt: et afledt ask (pris man betaler) rundes op, et afledt bid ned
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            ..with(ComplementRounding::Conservative)
+        });
+        f.apply(book("up", &[("0.375", "10")], &[("0.385", "10")]));
+        assert_eq!(f.state.down_ask_price, Some(dec("0.63")));
+        assert_eq!(f.state.down_bid_price, Some(dec("0.61")));
+    }
+
+    #[test]
+    fn real_and_derived_prices_share_the_tick_precision() {
+        let mut f = Fixture::new(OrderbookConfig {
+            derive_complement: true,
+            tick_size: dec("0.001"),
+            ..config()
+        });
+        f.apply(book("up", &[("0.4", "10")], &[("0.45", "10")]));
+
+        let s = &f.state;
+        let prices = [s.up_bid_price, s.up_ask_price, s.down_bid_price, s.down_ask_price];
+        let shown: Vec<_> = prices.iter().map(|p| p.unwrap().to_string()).collect();
+        assert_eq!(shown, ["0.400", "0.450", "0.550", "0.600"]);
+    }
+
+    #[test]
+    fn microprice_is_mid_when_balanced_and_near_the_thin_side_when_not() {
+        let mut f = Fixture::new(config());
+        f.apply(book("up", &[("0.40", "25")], &[("0.50", "25")]));
+        assert_eq!(f.state.up_microprice(), f.state.up_mid());
+
+        // 999 mod 1 i bid-size: prisen er på vej op mod ask
+        f.apply(book("down", &[("0.40", "999")], &[("0.50", "1")]));
+        assert_eq!(f.state.down_microprice(), Some(dec("0.4999")));
+        assert!(f.state.down_microprice() > f.state.down_mid());
+    }
+}
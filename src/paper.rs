@@ -0,0 +1,262 @@
+//! Papirhandel - simulér arb-handler mod dybden uden at sende ordrer.
+//!
+//! Hver `ArbDetected` fyldes mod bogens arb-trin (se `arb::rungs`) op til et
+//! loft pr. handel. Ved udløb afregnes hvert par til $1; ubalancerede ben kun
+//! hvis vinderen er kendt. Beholdningen holdes pr. marked, så en sen afregning
+//! af et udløbet marked ikke rører det næste.
+
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::arb::{ArbKind, ArbOpportunity, Rung};
+use crate::orderbook::Side;
+
+/// Én simuleret handel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaperTrade {
+    pub at_ms: i64,
+    pub kind: ArbKind,
+    /// Antal par handlet
+    pub pairs: Decimal,
+    /// Gennemsnitlig pris pr. par (UP + DOWN)
+    pub average_combined_cost: Decimal,
+    /// Kontantbevægelse - negativ ved køb
+    pub cash_flow: Decimal,
+}
+
+/// Beholdning i ét marked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    pub up_shares: Decimal,
+    pub down_shares: Decimal,
+}
+
+impl Position {
+    /// Par der holdes - udbetaler $1 uanset udfald
+    pub fn pairs(&self) -> Decimal {
+        self.up_shares.min(self.down_shares)
+    }
+}
+
+/// Simuleret beholdning og historik
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    /// Kontanter siden start - negativ når der er købt for mere end solgt
+    pub cash: Decimal,
+    /// Beholdning pr. marked (slug) der ikke er afregnet endnu
+    pub positions: HashMap<String, Position>,
+    pub trades: Vec<PaperTrade>,
+    /// Udbetalt ved afregning
+    pub settled: Decimal,
+}
+
+impl Ledger {
+    /// Beholdningen i `market` - tom hvis intet er handlet eller alt er afregnet
+    pub fn position(&self, market: &str) -> Position {
+        self.positions.get(market).copied().unwrap_or_default()
+    }
+
+    /// Par der holdes på tværs af markeder
+    pub fn pairs(&self) -> Decimal {
+        self.positions.values().map(Position::pairs).sum()
+    }
+
+    /// Realiseret PnL plus værdien af holdte par til $1
+    pub fn pnl(&self) -> Decimal {
+        self.cash + self.settled + self.pairs()
+    }
+}
+
+/// Papirhandler der fodres med arb-signaler
+#[derive(Debug, Clone)]
+pub struct PaperTrader {
+    /// Maks. antal par pr. handel
+    pub max_pairs: Decimal,
+    ledger: Ledger,
+}
+
+impl PaperTrader {
+    pub fn new(max_pairs: Decimal) -> Self {
+        Self {
+            max_pairs,
+            ledger: Ledger::default(),
+        }
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Fyld et arb i `market` mod `rungs` (fra `OrderbookHandle::arb_rungs(opportunity.kind)`).
+    /// Salg begrænses til de par der holdes i markedet. None hvis intet kunne handles.
+    pub fn on_arb(&mut self, market: &str, opportunity: &ArbOpportunity, rungs: &[Rung], at_ms: i64) -> Option<PaperTrade> {
+        let cap = match opportunity.kind {
+            ArbKind::BuyBoth => self.max_pairs,
+            ArbKind::SellBoth => self.max_pairs.min(self.ledger.position(market).pairs()),
+        };
+        let (pairs, cost) = fill(rungs, cap);
+        if pairs <= Decimal::ZERO {
+            return None;
+        }
+
+        let position = self.ledger.positions.entry(market.to_string()).or_default();
+        let cash_flow = match opportunity.kind {
+            ArbKind::BuyBoth => {
+                position.up_shares += pairs;
+                position.down_shares += pairs;
+                -cost
+            }
+            ArbKind::SellBoth => {
+                position.up_shares -= pairs;
+                position.down_shares -= pairs;
+                cost
+            }
+        };
+        self.ledger.cash += cash_flow;
+
+        let trade = PaperTrade {
+            at_ms,
+            kind: opportunity.kind,
+            pairs,
+            average_combined_cost: cost / pairs,
+            cash_flow,
+        };
+        debug!(
+            kind = ?trade.kind,
+            pairs = %trade.pairs,
+            avg_cost = %trade.average_combined_cost,
+            cash_flow = %trade.cash_flow,
+            pnl = %self.ledger.pnl(),
+            "papirhandel"
+        );
+        self.ledger.trades.push(trade);
+        Some(trade)
+    }
+
+    /// Afregn `market` ved udløb: hvert par giver $1, resten af det vindende ben $1 pr. share.
+    /// Uden kendt vinder afregnes kun parrene; ubalancen bliver stående til vinderen kendes.
+    pub fn settle(&mut self, market: &str, winner: Option<Side>) -> Decimal {
+        let Some(position) = self.ledger.positions.get_mut(market) else {
+            return Decimal::ZERO;
+        };
+        let pairs = position.pairs();
+        let mut payout = pairs;
+        position.up_shares -= pairs;
+        position.down_shares -= pairs;
+        match winner {
+            Some(Side::Up) => payout += position.up_shares,
+            Some(Side::Down) => payout += position.down_shares,
+            None => {}
+        }
+        if winner.is_some() || position.up_shares.is_zero() && position.down_shares.is_zero() {
+            self.ledger.positions.remove(market);
+        }
+        self.ledger.settled += payout;
+        debug!(market, payout = %payout, pnl = %self.ledger.pnl(), "papirafregning");
+        payout
+    }
+}
+
+/// Antal par og samlet pris for op til `cap` par langs trinene
+fn fill(rungs: &[Rung], cap: Decimal) -> (Decimal, Decimal) {
+    let (mut pairs, mut cost) = (Decimal::ZERO, Decimal::ZERO);
+    for rung in rungs {
+        if pairs >= cap {
+            break;
+        }
+        // Trinets marginale parpris er konstant mellem to kumulative punkter
+        let rung_cost = rung.average_combined_cost * rung.cumulative_size;
+        let step = rung.cumulative_size - pairs;
+        if step <= Decimal::ZERO {
+            continue;
+        }
+        let marginal = (rung_cost - cost) / step;
+        let take = step.min(cap - pairs);
+        pairs += take;
+        cost += take * marginal;
+    }
+    (pairs, cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn rung(cumulative_size: &str, average_combined_cost: &str) -> Rung {
+        Rung {
+            cumulative_size: dec(cumulative_size),
+            average_combined_cost: dec(average_combined_cost),
+        }
+    }
+
+    fn opportunity(kind: ArbKind) -> ArbOpportunity {
+        ArbOpportunity {
+            kind,
+            up_price: dec("0.45"),
+            down_price: dec("0.50"),
+            edge_cents: dec("5"),
+            size: dec("100"),
+        }
+    }
+
+    #[test]
+    fn a_partial_rung_is_filled_at_its_marginal_price() {
+        // 10 par á 0.90, så 20 mere der trækker snittet op til 0.92 - marginalt 0.93
+        let rungs = [rung("10", "0.90"), rung("30", "0.92")];
+        assert_eq!(fill(&rungs, dec("5")), (dec("5"), dec("4.50")));
+        assert_eq!(fill(&rungs, dec("20")), (dec("20"), dec("18.30")));
+        assert_eq!(fill(&rungs, dec("100")), (dec("30"), dec("27.60")));
+        assert_eq!(fill(&[], dec("10")), (Decimal::ZERO, Decimal::ZERO));
+    }
+
+    #[test]
+    fn selling_is_capped_at_the_pairs_held_in_the_market() {
+        let mut trader = PaperTrader::new(dec("50"));
+        let rungs = [rung("100", "1.05")];
+        assert_eq!(trader.on_arb("a", &opportunity(ArbKind::SellBoth), &rungs, 0), None);
+
+        trader.on_arb("a", &opportunity(ArbKind::BuyBoth), &[rung("8", "0.95")], 0).unwrap();
+        // Par i et andet marked kan ikke sælges her
+        assert_eq!(trader.on_arb("b", &opportunity(ArbKind::SellBoth), &rungs, 0), None);
+
+        let sold = trader.on_arb("a", &opportunity(ArbKind::SellBoth), &rungs, 1).unwrap();
+        assert_eq!((sold.pairs, sold.cash_flow), (dec("8"), dec("8.40")));
+        assert_eq!(trader.ledger().position("a"), Position::default());
+        assert_eq!(trader.ledger().pnl(), dec("0.80"));
+    }
+
+    #[test]
+    fn an_imbalance_is_only_settled_once_the_winner_is_known() {
+        let mut trader = PaperTrader::new(dec("50"));
+        trader.on_arb("a", &opportunity(ArbKind::BuyBoth), &[rung("10", "0.95")], 0).unwrap();
+        trader.ledger.positions.get_mut("a").unwrap().up_shares += dec("3");
+
+        // Parrene udbetales straks, de 3 ekstra UP står til vinderen kendes
+        assert_eq!(trader.settle("a", None), dec("10"));
+        assert_eq!(trader.ledger().position("a"), Position { up_shares: dec("3"), down_shares: Decimal::ZERO });
+        assert_eq!(trader.settle("a", Some(Side::Down)), Decimal::ZERO);
+        assert!(trader.ledger().positions.is_empty());
+
+        trader.on_arb("a", &opportunity(ArbKind::BuyBoth), &[rung("10", "0.95")], 0).unwrap();
+        trader.ledger.positions.get_mut("a").unwrap().up_shares += dec("3");
+        assert_eq!(trader.settle("a", Some(Side::Up)), dec("13"));
+        assert!(trader.ledger().positions.is_empty());
+    }
+
+    #[test]
+    fn a_late_settlement_leaves_the_next_market_alone() {
+        let mut trader = PaperTrader::new(dec("50"));
+        trader.on_arb("a", &opportunity(ArbKind::BuyBoth), &[rung("10", "0.95")], 0).unwrap();
+        assert_eq!(trader.settle("a", None), dec("10"));
+
+        // Næste marked handles før udfaldet af det første kendes
+        trader.on_arb("b", &opportunity(ArbKind::BuyBoth), &[rung("4", "0.96")], 0).unwrap();
+        assert_eq!(trader.settle("a", Some(Side::Up)), Decimal::ZERO);
+        assert_eq!(trader.ledger().position("b").pairs(), dec("4"));
+    }
+}
@@ -0,0 +1,251 @@
+//! Optagelse af top-of-book til JSONL - én linje pr. state-ændring, til backtesting.
+//!
+//! Format pr. linje: `ts_ms`, `token_up`, `token_down`, de otte pris/size-felter
+//! som decimal-strenge (null når siden ingen data har) og `expired` for rækker
+//! optaget efter markedets `end_ts`.
+
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::orderbook::{OrderbookState, ReliableReceiver};
+
+/// Hvor ofte bufferen skrives til disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Én optaget state
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub ts_ms: i64,
+    pub token_up: String,
+    pub token_down: String,
+    /// Optaget efter markedets udløb (false i optagelser uden feltet)
+    pub expired: bool,
+    /// Kun de otte pris/size-felter er sat; `last_update_ms` er `ts_ms`
+    pub state: OrderbookState,
+}
+
+/// Åben optagelse med bufret skrivning. Bufferen skrives ud når den droppes - også
+/// når tasken afbrydes eller går i panik - så allerede modtagne rækker ikke går tabt
+pub(crate) struct Recorder {
+    out: BufWriter<File>,
+    path: PathBuf,
+    token_up: String,
+    token_down: String,
+    /// Markedets udløb i ms, hvis kendt
+    end_ms: Option<i64>,
+}
+
+impl Recorder {
+    /// Åbn `path` til tilføjelse - filen oprettes hvis den ikke findes.
+    /// `end_ts` (unix-sekunder) markerer rækker efter udløb
+    pub(crate) fn open(path: PathBuf, token_up: String, token_down: String, end_ts: Option<i64>) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            out: BufWriter::new(file),
+            path,
+            token_up,
+            token_down,
+            end_ms: end_ts.map(|ts| ts * 1000),
+        })
+    }
+
+    /// Læg én række i bufferen
+    pub(crate) fn write(&mut self, state: &OrderbookState) -> io::Result<()> {
+        let expired = self.end_ms.is_some_and(|end| state.last_update_ms >= end);
+        let line = to_line(state, &self.token_up, &self.token_down, expired);
+        writeln!(self.out, "{}", line)
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let result = self.out.flush();
+        if std::thread::panicking() {
+            warn!(path = %self.path.display(), "optagelse skrevet ud under panik");
+        }
+        if let Err(e) = result {
+            warn!(path = %self.path.display(), error = %e, "flush af optagelse fejlede");
+        }
+    }
+}
+
+/// Skriv hvert snapshot fra `rx` til `path` indtil abonnementet lukker.
+/// Bufferen flushes periodisk og altid inden filen lukkes (se `Recorder`).
+pub(crate) async fn run(
+    path: PathBuf,
+    mut rx: ReliableReceiver,
+    token_up: String,
+    token_down: String,
+    end_ts: Option<i64>,
+) {
+    let mut recorder = match Recorder::open(path.clone(), token_up, token_down, end_ts) {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "kan ikke åbne optagelse");
+            return;
+        }
+    };
+    let mut flush_timer = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            state = rx.recv() => {
+                let Some(state) = state else { break };
+                if let Err(e) = recorder.write(&state) {
+                    warn!(error = %e, "skrivefejl i optagelse");
+                    return;
+                }
+            }
+            _ = flush_timer.tick() => {
+                if let Err(e) = recorder.flush() {
+                    warn!(error = %e, "flush af optagelse fejlede");
+                }
+            }
+        }
+    }
+}
+
+fn to_line(state: &OrderbookState, token_up: &str, token_down: &str, expired: bool) -> Value {
+    let d = |v: Option<Decimal>| v.map(|v| v.to_string());
+    json!({
+        "ts_ms": state.last_update_ms,
+        "token_up": token_up,
+        "token_down": token_down,
+        "up_bid_price": d(state.up_bid_price),
+        "up_bid_size": d(state.up_bid_size),
+        "up_ask_price": d(state.up_ask_price),
+        "up_ask_size": d(state.up_ask_size),
+        "down_bid_price": d(state.down_bid_price),
+        "down_bid_size": d(state.down_bid_size),
+        "down_ask_price": d(state.down_ask_price),
+        "down_ask_size": d(state.down_ask_size),
+        "expired": expired,
+    })
+}
+
+fn from_line(line: &str) -> Option<Record> {
+    let v: Value = serde_json::from_str(line).ok()?;
+    let d = |key: &str| v.get(key)?.as_str()?.parse::<Decimal>().ok();
+    let s = |key: &str| v.get(key).and_then(|x| x.as_str()).unwrap_or_default().to_string();
+    let ts_ms = v.get("ts_ms")?.as_i64()?;
+
+    Some(Record {
+        ts_ms,
+        token_up: s("token_up"),
+        token_down: s("token_down"),
+        expired: v.get("expired").and_then(|x| x.as_bool()).unwrap_or(false),
+        state: OrderbookState {
+            up_bid_price: d("up_bid_price"),
+            up_bid_size: d("up_bid_size"),
+            up_ask_price: d("up_ask_price"),
+            up_ask_size: d("up_ask_size"),
+            down_bid_price: d("down_bid_price"),
+            down_bid_size: d("down_bid_size"),
+            down_ask_price: d("down_ask_price"),
+            down_ask_size: d("down_ask_size"),
+            last_update_ms: ts_ms,
+            ..Default::default()
+        },
+    })
+}
+
+/// Læs en optagelse - records i den rækkefølge de blev skrevet.
+/// Tomme linjer springes over; en linje der ikke kan læses giver `InvalidData`.
+pub fn read(path: &Path) -> io::Result<impl Iterator<Item = io::Result<Record>>> {
+    let file = std::fs::File::open(path)?;
+    let lines = io::BufReader::new(file).lines().enumerate();
+    Ok(lines.filter_map(|(i, line)| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(from_line(&line).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("ugyldig record på linje {}", i + 1))
+        })),
+        Err(e) => Some(Err(e)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("recorder-{}-{}.jsonl", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn state(ts_ms: i64, up_bid: &str) -> OrderbookState {
+        OrderbookState {
+            up_bid_price: Some(up_bid.parse().unwrap()),
+            last_update_ms: ts_ms,
+            ..Default::default()
+        }
+    }
+
+    fn recorded(path: &Path) -> Vec<Record> {
+        read(path).unwrap().collect::<io::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn dropping_the_recorder_writes_buffered_rows() {
+        let path = temp_path("drop");
+        let mut recorder = Recorder::open(path.clone(), "up".into(), "down".into(), None).unwrap();
+        recorder.write(&state(1, "0.40")).unwrap();
+        recorder.write(&state(2, "0.41")).unwrap();
+        // Rækkerne ligger endnu kun i bufferen
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        drop(recorder);
+        let records = recorded(&path);
+        assert_eq!(records.iter().map(|r| r.ts_ms).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(records[1].state.up_bid_price, Some("0.41".parse().unwrap()));
+        assert_eq!(records[0].token_up, "up");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_panic_still_writes_buffered_rows() {
+        let path = temp_path("panic");
+        let thread_path = path.clone();
+        let result = std::thread::spawn(move || {
+            let mut recorder = Recorder::open(thread_path, "up".into(), "down".into(), None).unwrap();
+            recorder.write(&state(1, "0.40")).unwrap();
+            panic!("fejl efter skrivning");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert_eq!(recorded(&path).len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rows_from_end_ts_on_are_marked_expired() {
+        let path = temp_path("expired");
+        let mut recorder = Recorder::open(path.clone(), "up".into(), "down".into(), Some(10)).unwrap();
+        for ts_ms in [9_999, 10_000, 12_000] {
+            recorder.write(&state(ts_ms, "0.40")).unwrap();
+        }
+        drop(recorder);
+
+        let expired: Vec<_> = recorded(&path).iter().map(|r| r.expired).collect();
+        assert_eq!(expired, [false, true, true]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rows_without_the_field_are_not_expired() {
+        let line = r#"{"ts_ms":5,"token_up":"up","token_down":"down","up_bid_price":"0.40"}"#;
+        let record = from_line(line).unwrap();
+        assert!(!record.expired);
+        assert_eq!(record.state.up_bid_price, Some("0.40".parse().unwrap()));
+    }
+}
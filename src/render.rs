@@ -0,0 +1,251 @@
+//! Visning af markedets state - adskilt fra update-loopet, så en TUI,
+//! JSON-emitter el.lign. kan sættes ind i stedet.
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::arb;
+use crate::clock::{self, Clock};
+use crate::market::Market;
+use crate::orderbook::{self, ConnectionStatus, LegHealth, OrderbookState};
+
+/// Tegner én opdatering af et marked
+pub trait Renderer {
+    /// `ttl` er sekunder til `end_ts` (negativ efter udløb); `expired` er sat i efter-vinduet
+    fn render(&mut self, market: &Market, state: &OrderbookState, status: ConnectionStatus, ttl: i64, expired: bool);
+}
+
+/// Én linje der overskrives på stedet med `\r` - til en terminal
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalLine {
+    /// Vis også spread i cents/basispoint
+    pub verbose: bool,
+}
+
+impl Renderer for TerminalLine {
+    fn render(&mut self, market: &Market, state: &OrderbookState, status: ConnectionStatus, ttl: i64, expired: bool) {
+        print!("\r{}    ", status_line(market, state, status, ttl, expired, self.verbose));
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Én linje pr. opdatering med tidsstempel - til filer og pipes
+#[derive(Debug, Clone)]
+pub struct LineLog {
+    pub verbose: bool,
+    /// Kilde til linjens tidsstempel - samme ur som sessionen
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for LineLog {
+    fn default() -> Self {
+        Self {
+            verbose: false,
+            clock: clock::system(),
+        }
+    }
+}
+
+impl Renderer for LineLog {
+    fn render(&mut self, market: &Market, state: &OrderbookState, status: ConnectionStatus, ttl: i64, expired: bool) {
+        let now = chrono::DateTime::from_timestamp_millis(self.clock.now_ms())
+            .map(|t| t.format("%H:%M:%S%.3f").to_string())
+            .unwrap_or_default();
+        println!("{} {}", now, status_line(market, state, status, ttl, expired, self.verbose));
+    }
+}
+
+/// Ét JSON-objekt pr. linje på stdout - til `jq` og andre processer
+#[derive(Debug, Clone)]
+pub struct JsonLines {
+    /// Kilde til `ts_ms` - samme ur som sessionen
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for JsonLines {
+    fn default() -> Self {
+        Self { clock: clock::system() }
+    }
+}
+
+/// Linjen `JsonLines` skriver: markedet, tid og state fladet ud
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    ts_ms: i64,
+    slug: &'a str,
+    token_up: &'a str,
+    token_down: &'a str,
+    status: ConnectionStatus,
+    ttl: i64,
+    expired: bool,
+    /// `OrderbookState::no_arb_residual` som tal
+    residual: Option<f64>,
+    #[serde(flatten)]
+    state: &'a OrderbookState,
+}
+
+impl Renderer for JsonLines {
+    fn render(&mut self, market: &Market, state: &OrderbookState, status: ConnectionStatus, ttl: i64, expired: bool) {
+        let line = JsonLine {
+            ts_ms: self.clock.now_ms(),
+            slug: &market.slug,
+            token_up: &market.token_up,
+            token_down: &market.token_down,
+            status,
+            ttl,
+            expired,
+            residual: state.no_arb_residual().and_then(|r| r.to_f64()),
+            state,
+        };
+        match serde_json::to_string(&line) {
+            Ok(json) => println!("{}", json),
+            Err(e) => tracing::warn!(error = %e, "kan ikke serialisere state"),
+        }
+    }
+}
+
+/// Statuslinjen for markedet, uden linjeskift
+pub fn status_line(
+    m: &Market,
+    state: &OrderbookState,
+    status: ConnectionStatus,
+    ttl: i64,
+    expired: bool,
+    verbose: bool,
+) -> String {
+    let price = |v| fmt_price(v, m.tick_size);
+    let up_label = m.labels.up.to_uppercase();
+    let down_label = m.labels.down.to_uppercase();
+    let marker = match status {
+        _ if expired => "[EFTER] ",
+        ConnectionStatus::BooksLive => "",
+        ConnectionStatus::Stale => "[FORÆLDET] ",
+        ConnectionStatus::Connected => "[VENTER PÅ BOG] ",
+        ConnectionStatus::Connecting => "[FORBINDER] ",
+        ConnectionStatus::Disconnected | ConnectionStatus::Reconnecting => "[GENFORBINDER] ",
+    };
+
+    let mut line = format!(
+        "{}TTL:{:>4}s | {} {}/{} - {}/{}{} | {} {}/{} - {}/{}{}",
+        marker,
+        ttl,
+        up_label,
+        price(state.up_bid_price), fmt(state.up_bid_size),
+        price(state.up_ask_price), fmt(state.up_ask_size),
+        leg_flag(state.up_wide_spread, state.up_health),
+        down_label,
+        price(state.down_bid_price), fmt(state.down_bid_size),
+        price(state.down_ask_price), fmt(state.down_ask_size),
+        leg_flag(state.down_wide_spread, state.down_health),
+    );
+    if let Some(a) = arb::detect(state) {
+        let kind = match a.kind {
+            arb::ArbKind::BuyBoth => "KØB",
+            arb::ArbKind::SellBoth => "SÆLG",
+        };
+        line.push_str(&format!(" | *** ARB {} +{:.1}c x {} ***", kind, a.edge_cents, a.size));
+    }
+    if verbose {
+        line.push_str(&format!(
+            " | SPREAD {} {} {} {}",
+            up_label,
+            fmt_spread(state.up_spread(), state.up_mid()),
+            down_label,
+            fmt_spread(state.down_spread(), state.down_mid()),
+        ));
+    }
+    line
+}
+
+/// Markering af et ben der ikke er til at handle på
+fn leg_flag(wide_spread: bool, health: LegHealth) -> &'static str {
+    if matches!(health, LegHealth::Disabled { .. }) {
+        " (fra)"
+    } else if wide_spread {
+        " (bred)"
+    } else {
+        ""
+    }
+}
+
+/// Pris med markedets tick-præcision, tom hvis siden ingen data har
+fn fmt_price(v: Option<Decimal>, tick: Decimal) -> String {
+    fmt(v.map(|v| orderbook::at_tick_scale(v, tick)))
+}
+
+/// Size som modtaget, tom hvis siden ingen data har
+fn fmt(v: Option<Decimal>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Spread i cents og basispoint af mid, `--` hvis en side mangler
+fn fmt_spread(spread: Option<Decimal>, mid: Option<Decimal>) -> String {
+    match (spread, mid) {
+        (Some(spread), Some(mid)) if mid > Decimal::ZERO => {
+            let cents = spread * Decimal::ONE_HUNDRED;
+            let bps = spread / mid * Decimal::from(10_000);
+            format!("{:.1}c/{:.0}bp", cents, bps)
+        }
+        _ => "--".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::Labels;
+
+    fn market(labels: Labels, tick_size: &str) -> Market {
+        Market {
+            slug: "will-it-rain".to_string(),
+            title: "Will it rain?".to_string(),
+            end_ts: 0,
+            token_up: "1".to_string(),
+            token_down: "2".to_string(),
+            labels,
+            outcomes: Vec::new(),
+            tick_size: tick_size.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn status_line_uses_the_market_labels() {
+        let market = market(Labels::new("Yes", "No"), "0.01");
+        let dec = |s: &str| Some(s.parse::<Decimal>().unwrap());
+        let state = OrderbookState {
+            up_bid_price: dec("0.40"),
+            up_bid_size: dec("10"),
+            up_ask_price: dec("0.42"),
+            up_ask_size: dec("5"),
+            down_bid_price: dec("0.57"),
+            down_bid_size: dec("7"),
+            down_ask_price: dec("0.59"),
+            down_ask_size: dec("3"),
+            ..Default::default()
+        };
+
+        let line = status_line(&market, &state, ConnectionStatus::BooksLive, 30, false, false);
+        assert_eq!(line, "TTL:  30s | YES 0.40/10 - 0.42/5 | NO 0.57/7 - 0.59/3");
+        let verbose = status_line(&market, &state, ConnectionStatus::BooksLive, 30, false, true);
+        assert!(verbose.starts_with(&line) && verbose.contains("| SPREAD YES 2.0c/"), "{}", verbose);
+        assert!(verbose.contains(" NO 2.0c/"), "{}", verbose);
+    }
+
+    #[test]
+    fn prices_are_shown_with_the_market_tick_precision() {
+        let dec = |s: &str| Some(s.parse::<Decimal>().unwrap());
+        let state = OrderbookState {
+            up_bid_price: dec("0.4"),
+            up_bid_size: dec("10"),
+            up_ask_price: dec("0.455"),
+            up_ask_size: dec("5"),
+            ..Default::default()
+        };
+
+        let line = status_line(&market(Labels::default(), "0.001"), &state, ConnectionStatus::BooksLive, 30, false, false);
+        assert_eq!(line, "TTL:  30s | UP 0.400/10 - 0.455/5 | DOWN / - /");
+    }
+}
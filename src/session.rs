@@ -0,0 +1,304 @@
+//! Sessioner - find marked, start orderbook-lag, overvåg til udløb, og forfra med næste slot.
+//! `MarketManager` følger flere markedsserier side om side og genopdager dem på kommando.
+
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::arb::{ArbOpportunity, Rung};
+use crate::market::{self, DiscoveryError, Market};
+use crate::error::Error;
+use crate::orderbook::{self, ConnectionStatus, FeedEvent, OrderbookConfig, OrderbookHandle, OrderbookState};
+
+/// Opsætning af en kørende session
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Skabelon for orderbook-laget - tokens og labels sættes pr. marked.
+    /// Dens `clock` bruges også til TTL og udløb.
+    pub orderbook: OrderbookConfig,
+    /// Sekunder bogen fortsat observeres efter `end_ts`
+    pub post_expiry_observe_secs: i64,
+    /// Pause før discovery prøves igen når næste slot ikke er live endnu
+    pub retry_delay: Duration,
+    /// Gem det aktuelle marked her ved hver discovery og rollover (se `market::resume`)
+    pub state_path: Option<PathBuf>,
+    /// Opgiv et marked der ikke har leveret et eneste `StateUpdated` inden for denne tid
+    /// efter start, og gå videre til næste slot (None = vent til udløb).
+    /// Adskilt fra orderbook-lagets staleness-vagt, der først gælder når bøgerne er live
+    pub first_data_deadline: Option<Duration>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            orderbook: OrderbookConfig::default(),
+            post_expiry_observe_secs: 0,
+            retry_delay: Duration::from_secs(5),
+            state_path: None,
+            first_data_deadline: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Hvad der sker i sessionen - leveres til kalderens callback
+#[derive(Debug)]
+pub enum SessionEvent<'a> {
+    /// Discovery fejlede - prøves igen efter `retry_delay`
+    DiscoveryFailed(&'a DiscoveryError),
+    /// Nyt marked fundet og orderbook-laget startet
+    Started(&'a Market),
+    /// Ny state eller forbindelsesstatus, eller blot et tick for TTL
+    Update {
+        market: &'a Market,
+        state: &'a OrderbookState,
+        status: ConnectionStatus,
+        /// Sekunder til `end_ts`, negativ efter udløb
+        ttl: i64,
+        /// Markedet er udløbet og observeres i efter-vinduet
+        expired: bool,
+    },
+    /// `end_ts` er passeret
+    Expired(&'a Market),
+    /// Fejl rapporteret af orderbook-laget - laget genforbinder selv
+    FeedError(&'a Error),
+    /// Ny arb-mulighed, med trinene over hele dybden i dens retning
+    Arb {
+        market: &'a Market,
+        opportunity: &'a ArbOpportunity,
+        rungs: &'a [Rung],
+    },
+    /// Ingen data inden for `first_data_deadline` - markedet opgives
+    NoData(&'a Market),
+    /// Orderbook-laget er lukket ned for markedet
+    Ended(&'a Market),
+}
+
+/// Kør discover → spawn → overvåg → udløb i ring, et slot ad gangen.
+///
+/// `discover` kaldes for hvert nyt slot (mock den i tests). Returnerer
+/// discovery samme eller et ældre marked end det netop afsluttede, er næste
+/// slot ikke live endnu, og der prøves igen efter `retry_delay`.
+/// Kører indtil `on_event` returnerer `Break`.
+pub async fn run_session<D, F, H>(mut discover: D, config: &SessionConfig, mut on_event: H)
+where
+    D: FnMut() -> F,
+    F: Future<Output = Result<Market, DiscoveryError>>,
+    H: FnMut(SessionEvent<'_>) -> ControlFlow<()>,
+{
+    let mut last_end = i64::MIN;
+    loop {
+        let market = match discover().await {
+            Ok(market) if market.end_ts > last_end => market,
+            Ok(_) => {
+                tokio::time::sleep(config.retry_delay).await;
+                continue;
+            }
+            Err(e) => {
+                if on_event(SessionEvent::DiscoveryFailed(&e)).is_break() {
+                    return;
+                }
+                tokio::time::sleep(config.retry_delay).await;
+                continue;
+            }
+        };
+        last_end = market.end_ts;
+        if let Some(path) = &config.state_path {
+            if let Err(e) = market::save_last(path, &market) {
+                warn!(path = %path.display(), error = %e, "kan ikke gemme marked");
+            }
+        }
+
+        if on_event(SessionEvent::Started(&market)).is_break() {
+            return;
+        }
+        if watch(&market, config, &mut on_event).await.is_break() {
+            return;
+        }
+    }
+}
+
+/// Overvåg ét marked til efter-vinduet er slut eller feedet lukker
+async fn watch<H>(market: &Market, config: &SessionConfig, on_event: &mut H) -> ControlFlow<()>
+where
+    H: FnMut(SessionEvent<'_>) -> ControlFlow<()>,
+{
+    let handle = orderbook::spawn(config.orderbook.clone().with_market(market));
+
+    let mut updates = handle.subscribe_updates();
+    let mut status_rx = handle.subscribe_status();
+    let mut events = handle.subscribe_events();
+    let mut status = ConnectionStatus::Connecting;
+    let mut expired = false;
+    let mut has_data = false;
+    let no_data_at = config.first_data_deadline.map(|d| tokio::time::Instant::now() + d);
+    // Tick så TTL og udløb også opdateres når bogen står stille
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+    let flow = loop {
+        tokio::select! {
+            res = updates.recv() => match res {
+                Err(RecvError::Closed) => break ControlFlow::Continue(()),
+                // Visningen tegner blot nyeste state - et hul er ikke fatalt, men meldes
+                Err(RecvError::Lagged(n)) => {
+                    has_data = true;
+                    debug!(skipped = n, "updates sprunget over");
+                }
+                Ok(_) => has_data = true,
+            },
+            // Aktivt men ingen bog - typisk illikvidt eller ikke åbnet for handel endnu
+            _ = tokio::time::sleep_until(no_data_at.unwrap_or_else(tokio::time::Instant::now)),
+                if no_data_at.is_some() && !has_data =>
+            {
+                warn!(slug = %market.slug, after = ?config.first_data_deadline, "ingen data fra markedet - opgiver");
+                if on_event(SessionEvent::NoData(market)).is_break() {
+                    break ControlFlow::Break(());
+                }
+                break ControlFlow::Continue(());
+            }
+            Ok(s) = status_rx.recv() => status = s,
+            Ok(event) = events.recv() => {
+                let flow = match event {
+                    FeedEvent::Error(e) => on_event(SessionEvent::FeedError(&e)),
+                    FeedEvent::ArbDetected(opportunity) => {
+                        let rungs = handle.arb_rungs(opportunity.kind).await;
+                        on_event(SessionEvent::Arb { market, opportunity: &opportunity, rungs: &rungs })
+                    }
+                    _ => ControlFlow::Continue(()),
+                };
+                if flow.is_break() {
+                    break ControlFlow::Break(());
+                }
+                continue;
+            }
+            _ = tick.tick() => {}
+        }
+
+        let ttl = market.end_ts - config.orderbook.clock.now_secs();
+        if ttl <= 0 && !expired {
+            expired = true;
+            if on_event(SessionEvent::Expired(market)).is_break() {
+                break ControlFlow::Break(());
+            }
+        }
+        if expired && ttl <= -config.post_expiry_observe_secs {
+            break ControlFlow::Continue(());
+        }
+
+        let state = handle.get_current_state().await;
+        let event = SessionEvent::Update {
+            market,
+            state: &state,
+            status,
+            ttl,
+            expired,
+        };
+        if on_event(event).is_break() {
+            break ControlFlow::Break(());
+        }
+    };
+
+    handle.shutdown().await;
+    match flow {
+        ControlFlow::Continue(()) => on_event(SessionEvent::Ended(market)),
+        ControlFlow::Break(()) => flow,
+    }
+}
+
+/// Ændring i de markeder en `MarketManager` følger
+#[derive(Debug, Clone)]
+pub enum ManagerEvent {
+    /// Serien har fået et nyt marked - `from` er det forrige, hvis der var et
+    MarketRolled { series: usize, from: Option<Market>, to: Market },
+    /// Serien har ikke længere et aktivt marked, og dets orderbook-lag er lukket
+    MarketClosed { series: usize, market: Market },
+}
+
+/// Følger ét marked pr. markedsserie side om side, hver med sit eget orderbook-lag.
+///
+/// `discover(series)` finder seriens aktive marked (typisk `market::find_active_with`
+/// med seriens `DiscoveryParams`; mock den i tests). Intet sker af sig selv - kald
+/// `refresh_markets` for at opdage nye slots og lukke dem der er væk.
+pub struct MarketManager<D> {
+    discover: D,
+    template: OrderbookConfig,
+    tracked: Vec<Option<(Market, OrderbookHandle)>>,
+    event_tx: broadcast::Sender<ManagerEvent>,
+}
+
+impl<D, F> MarketManager<D>
+where
+    D: FnMut(usize) -> F,
+    F: Future<Output = Result<Market, DiscoveryError>>,
+{
+    /// Manager for `series` serier uden markeder endnu. `template` bruges til hvert
+    /// orderbook-lag med tokens og labels fra markedet
+    pub fn new(series: usize, template: OrderbookConfig, discover: D) -> Self {
+        Self {
+            discover,
+            template,
+            tracked: (0..series).map(|_| None).collect(),
+            event_tx: broadcast::channel(64).0,
+        }
+    }
+
+    /// Kør discovery for alle serier nu: start orderbook-lag for nye markeder og luk
+    /// dem der er afløst eller uden aktivt slot. Andre discovery-fejl (fx HTTP)
+    /// lader seriens nuværende marked være
+    pub async fn refresh_markets(&mut self) {
+        for series in 0..self.tracked.len() {
+            match (self.discover)(series).await {
+                Ok(market) => {
+                    if self.tracked[series].as_ref().is_some_and(|(m, _)| m.slug == market.slug) {
+                        continue;
+                    }
+                    info!(series, slug = %market.slug, "nyt marked");
+                    let handle = orderbook::spawn(self.template.clone().with_market(&market));
+                    let from = match self.tracked[series].replace((market.clone(), handle)) {
+                        Some((old, handle)) => {
+                            handle.shutdown().await;
+                            Some(old)
+                        }
+                        None => None,
+                    };
+                    let _ = self.event_tx.send(ManagerEvent::MarketRolled { series, from, to: market });
+                }
+                Err(DiscoveryError::NoActiveSlot { .. }) => {
+                    if let Some((market, handle)) = self.tracked[series].take() {
+                        info!(series, slug = %market.slug, "marked lukket");
+                        handle.shutdown().await;
+                        let _ = self.event_tx.send(ManagerEvent::MarketClosed { series, market });
+                    }
+                }
+                Err(e) => warn!(series, error = %e, "discovery fejlede - beholder markedet"),
+            }
+        }
+    }
+}
+
+impl<D> MarketManager<D> {
+    /// Subscribe til `MarketRolled`/`MarketClosed`
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ManagerEvent> {
+        self.event_tx.subscribe()
+    }
+
+    /// De markeder der følges nu, i serie-rækkefølge
+    pub fn markets(&self) -> Vec<&Market> {
+        self.tracked.iter().flatten().map(|(m, _)| m).collect()
+    }
+
+    /// Orderbook-laget for seriens nuværende marked
+    pub fn handle(&self, series: usize) -> Option<&OrderbookHandle> {
+        self.tracked.get(series)?.as_ref().map(|(_, h)| h)
+    }
+
+    /// Luk alle orderbook-lag og vent til de er lukket
+    pub async fn shutdown(self) {
+        for (_, handle) in self.tracked.into_iter().flatten() {
+            handle.shutdown().await;
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Sizing - hvor mange parrede shares kan købes inden for et budget.
+
+use rust_decimal::Decimal;
+
+use crate::book::Level;
+
+/// Maks. antal parrede shares (1 UP + 1 DOWN) der kan købes for `budget`,
+/// så prisen på hvert par inkl. fee er under $1.
+///
+/// `up_asks` og `down_asks` er bøgernes rigtige niveauer med bedste først (som
+/// `OrderbookHandle::get_depth`) - afledte komplementer indgår aldrig. Begge ben
+/// gås igennem i takt som i `arb::rungs`, og der stoppes ved det første par der
+/// ikke længere er profitabelt eller når budgettet er brugt.
+///
+/// Returnerer `(shares, forventet profit)` med shares rundet ned til hele stk.
+/// `(0, 0)` hvis et ben mangler eller intet par er profitabelt.
+pub fn max_pairs_for_budget(
+    up_asks: &[Level],
+    down_asks: &[Level],
+    budget: Decimal,
+    fee_bps: Decimal,
+) -> (Decimal, Decimal) {
+    if budget <= Decimal::ZERO {
+        return (Decimal::ZERO, Decimal::ZERO);
+    }
+    let fee = Decimal::ONE + fee_bps / Decimal::from(10_000);
+    let (shares, _) = fill(up_asks, down_asks, fee, budget, None);
+    // Anden gang med hele shares - prisen er den for netop de par
+    let (shares, cost) = fill(up_asks, down_asks, fee, budget, Some(shares.floor()));
+    (shares, shares - cost)
+}
+
+/// Køb par langs begge bens asks inden for `budget` og evt. `cap` par.
+/// Returnerer `(par, samlet pris inkl. fee)`
+fn fill(up: &[Level], down: &[Level], fee: Decimal, budget: Decimal, cap: Option<Decimal>) -> (Decimal, Decimal) {
+    let (mut i, mut j) = (0, 0);
+    let (mut up_used, mut down_used) = (Decimal::ZERO, Decimal::ZERO);
+    let (mut shares, mut cost) = (Decimal::ZERO, Decimal::ZERO);
+
+    while i < up.len() && j < down.len() {
+        // Pris pr. par inkl. fee på det handlede beløb
+        let pair_cost = (up[i].price + down[j].price) * fee;
+        if pair_cost <= Decimal::ZERO || pair_cost >= Decimal::ONE {
+            break;
+        }
+        let depth = (up[i].size - up_used).min(down[j].size - down_used);
+        let mut qty = depth.min((budget - cost) / pair_cost);
+        if let Some(cap) = cap {
+            qty = qty.min(cap - shares);
+        }
+        if qty <= Decimal::ZERO {
+            break;
+        }
+        shares += qty;
+        cost += qty * pair_cost;
+        // Budget eller loft nået før niveauet var brugt op
+        if qty < depth {
+            break;
+        }
+        up_used += qty;
+        down_used += qty;
+        if up_used == up[i].size {
+            i += 1;
+            up_used = Decimal::ZERO;
+        }
+        if down_used == down[j].size {
+            j += 1;
+            down_used = Decimal::ZERO;
+        }
+    }
+    (shares, cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    fn levels(levels: &[(&str, &str)]) -> Vec<Level> {
+        levels.iter().map(|(price, size)| Level { price: dec(price), size: dec(size) }).collect()
+    }
+
+    fn deep() -> (Vec<Level>, Vec<Level>) {
+        (
+            levels(&[("0.45", "5"), ("0.47", "10"), ("0.55", "100")]),
+            levels(&[("0.50", "3"), ("0.51", "20")]),
+        )
+    }
+
+    #[test]
+    fn thin_book_is_limited_by_the_smaller_top_level() {
+        let (up, down) = (levels(&[("0.45", "5")]), levels(&[("0.50", "3")]));
+        assert_eq!(max_pairs_for_budget(&up, &down, dec("100"), Decimal::ZERO), (dec("3"), dec("0.15")));
+    }
+
+    #[test]
+    fn deep_book_walks_levels_until_a_pair_is_no_longer_profitable() {
+        let (up, down) = deep();
+        // 3 @ 0.95, 2 @ 0.96, 10 @ 0.98 - 0.55 + 0.51 er over $1
+        assert_eq!(max_pairs_for_budget(&up, &down, dec("100"), Decimal::ZERO), (dec("15"), dec("0.43")));
+    }
+
+    #[test]
+    fn budget_caps_the_walk_at_whole_shares() {
+        let (up, down) = deep();
+        // 4.77 for de første fem par - resten rækker kun til en brøkdel af det sjette
+        assert_eq!(max_pairs_for_budget(&up, &down, dec("5"), Decimal::ZERO), (dec("5"), dec("0.23")));
+    }
+
+    #[test]
+    fn fees_stop_the_walk_earlier() {
+        let (up, down) = deep();
+        // Med 3% koster 0.98-parret 1.0094
+        let (shares, profit) = max_pairs_for_budget(&up, &down, dec("100"), dec("300"));
+        assert_eq!(shares, dec("5"));
+        assert_eq!(profit, dec("5") - (dec("2.85") + dec("1.92")) * dec("1.03"));
+    }
+
+    #[test]
+    fn missing_leg_or_no_arb_gives_nothing() {
+        let (up, down) = deep();
+        let none = (Decimal::ZERO, Decimal::ZERO);
+        assert_eq!(max_pairs_for_budget(&up, &[], dec("100"), Decimal::ZERO), none);
+        assert_eq!(max_pairs_for_budget(&levels(&[("0.50", "5")]), &down, dec("100"), Decimal::ZERO), none);
+        assert_eq!(max_pairs_for_budget(&up, &down, Decimal::ZERO, Decimal::ZERO), none);
+    }
+}
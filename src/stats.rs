@@ -0,0 +1,167 @@
+//! Driftstællere - kumulativt antal plus en henfaldende rate,
+//! så monitorering kan reagere på en nylig spike frem for et tal der kun vokser.
+
+use std::time::Duration;
+
+/// Tæller med kumulativt total og eksponentielt henfaldende rate (hændelser/sek)
+#[derive(Debug, Clone, Copy)]
+pub struct DecayingCounter {
+    pub total: u64,
+    rate: f64,
+    last_ms: i64,
+    tau_secs: f64,
+}
+
+impl DecayingCounter {
+    /// `tau` er tidskonstanten - efter `tau` uden hændelser er raten faldet til ~37%
+    pub fn new(tau: Duration) -> Self {
+        Self {
+            total: 0,
+            rate: 0.0,
+            last_ms: 0,
+            tau_secs: tau.as_secs_f64().max(f64::EPSILON),
+        }
+    }
+
+    /// Registrér én hændelse
+    pub fn record(&mut self, now_ms: i64) {
+        self.record_n(1, now_ms);
+    }
+
+    /// Registrér `n` hændelser på én gang (fx antal droppede updates ved lag)
+    pub fn record_n(&mut self, n: u64, now_ms: i64) {
+        self.rate = self.rate(now_ms) + n as f64 / self.tau_secs;
+        self.last_ms = now_ms;
+        self.total += n;
+    }
+
+    /// Aktuel rate i hændelser pr. sekund, henfaldet frem til `now_ms`
+    pub fn rate(&self, now_ms: i64) -> f64 {
+        let dt = (now_ms - self.last_ms).max(0) as f64 / 1000.0;
+        self.rate * (-dt / self.tau_secs).exp()
+    }
+}
+
+impl Default for DecayingCounter {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(60))
+    }
+}
+
+/// Antal samples i latency-vinduet
+const LATENCY_WINDOW: usize = 256;
+
+/// Seneste latenser (ms) i en fast ringbuffer - p50/p99 over vinduet
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyWindow {
+    samples: [i64; LATENCY_WINDOW],
+    len: usize,
+    next: usize,
+    last: Option<i64>,
+}
+
+impl LatencyWindow {
+    pub fn record(&mut self, latency_ms: i64) {
+        self.samples[self.next] = latency_ms;
+        self.next = (self.next + 1) % LATENCY_WINDOW;
+        self.len = (self.len + 1).min(LATENCY_WINDOW);
+        self.last = Some(latency_ms);
+    }
+
+    /// Seneste måling
+    pub fn last(&self) -> Option<i64> {
+        self.last
+    }
+
+    /// Antal målinger i vinduet
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// `p`-fraktil (0.0-1.0) over vinduet, None uden målinger
+    pub fn percentile(&self, p: f64) -> Option<i64> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut sorted = self.samples[..self.len].to_vec();
+        sorted.sort_unstable();
+        let rank = (p.clamp(0.0, 1.0) * (self.len - 1) as f64).round() as usize;
+        Some(sorted[rank])
+    }
+
+    pub fn p50(&self) -> Option<i64> {
+        self.percentile(0.5)
+    }
+
+    pub fn p99(&self) -> Option<i64> {
+        self.percentile(0.99)
+    }
+}
+
+impl Default for LatencyWindow {
+    fn default() -> Self {
+        Self {
+            samples: [0; LATENCY_WINDOW],
+            len: 0,
+            next: 0,
+            last: None,
+        }
+    }
+}
+
+/// Tællere for et orderbook data layer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FeedStats {
+    /// Beskeder der ikke kunne parses som JSON
+    pub parse_failures: DecayingCounter,
+    /// Snapshots der ikke kunne leveres til en pålidelig subscriber
+    pub dropped_updates: DecayingCounter,
+    /// Genforbindelser efter tabt WebSocket
+    pub reconnects: DecayingCounter,
+    /// Forbindelsesforsøg udskudt af `min_reconnect_interval`
+    pub reconnect_floor_delays: DecayingCounter,
+    /// `StateUpdated` som `updates_stream`-forbrugere sprang over pga. broadcast-lag
+    pub lagged_updates: DecayingCounter,
+    /// Frisk bog hentet fordi beskeder manglede eller ikke hang sammen
+    pub resyncs: DecayingCounter,
+    /// `modtaget - server timestamp` for anvendte beskeder der bærer et timestamp
+    pub latency: LatencyWindow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decayed_rate_reflects_recency_not_the_total() {
+        let tau = Duration::from_secs(10);
+        let mut old = DecayingCounter::new(tau);
+        let mut recent = DecayingCounter::new(tau);
+        old.record_n(50, 0);
+        recent.record_n(50, 55_000);
+        let now_ms = 60_000;
+
+        assert_eq!((old.total, recent.total), (50, 50));
+        assert!(old.rate(now_ms) < 0.1, "{}", old.rate(now_ms));
+        assert!(recent.rate(now_ms) > 1.0, "{}", recent.rate(now_ms));
+    }
+
+    #[test]
+    fn rate_decays_by_one_tau_and_bursts_add_up() {
+        let mut counter = DecayingCounter::new(Duration::from_secs(10));
+        for _ in 0..20 {
+            counter.record(1_000);
+        }
+        assert!((counter.rate(1_000) - 2.0).abs() < 1e-9);
+        assert!((counter.rate(11_000) - 2.0 / std::f64::consts::E).abs() < 1e-9);
+
+        // En ny burst lægges oven i det der er tilbage af den gamle
+        counter.record_n(10, 11_000);
+        assert_eq!(counter.total, 30);
+        assert!((counter.rate(11_000) - (2.0 / std::f64::consts::E + 1.0)).abs() < 1e-9);
+    }
+}
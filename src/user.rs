@@ -0,0 +1,249 @@
+//! Privat `user`-kanal - egne åbne ordrer og fills, adskilt fra den offentlige bog.
+//!
+//! Kører kun når `OrderbookConfig::auth` er sat. Forbindelsen er sin egen
+//! WebSocket og deler intet state med bog-laget, så rene markedsdata-brugere
+//! ikke betaler for den.
+
+use futures_util::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+use crate::error::Error;
+
+/// Polymarkets autentificerede user-kanal
+pub const DEFAULT_USER_WS_URL: &str = "wss://ws-subscriptions-clob.polymarket.com/ws/user";
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// CLOB API-nøgle - vises aldrig i `Debug`
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiCredentials {
+    pub api_key: String,
+    pub secret: String,
+    pub passphrase: String,
+}
+
+impl std::fmt::Debug for ApiCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiCredentials")
+            .field("api_key", &"<redacted>")
+            .field("secret", &"<redacted>")
+            .field("passphrase", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Køb eller salg
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+/// En åben ordre
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+    pub id: String,
+    pub asset_id: String,
+    pub side: OrderSide,
+    pub price: Decimal,
+    pub original_size: Decimal,
+    pub size_matched: Decimal,
+}
+
+impl Order {
+    /// Hvad der endnu ikke er matchet
+    pub fn remaining(&self) -> Decimal {
+        (self.original_size - self.size_matched).max(Decimal::ZERO)
+    }
+}
+
+/// En handel på en af egne ordrer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    pub id: String,
+    pub asset_id: String,
+    pub side: OrderSide,
+    pub price: Decimal,
+    pub size: Decimal,
+    /// `MATCHED`, `MINED`, `CONFIRMED`, `RETRYING` eller `FAILED`
+    pub status: String,
+}
+
+/// Egne ordrer og fills som meldt på user-kanalen
+#[derive(Debug, Clone, Default)]
+pub struct UserState {
+    /// Åbne ordrer efter ordre-id
+    pub open_orders: HashMap<String, Order>,
+    /// Fills i den rækkefølge de første gang blev set; statusændringer opdaterer på stedet
+    pub fills: Vec<Fill>,
+}
+
+/// Kør user-kanalen med genforbindelse indtil `shutdown_rx` udløses
+pub(crate) async fn run(
+    url: String,
+    auth: ApiCredentials,
+    state: Arc<RwLock<UserState>>,
+    mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match run_connection(&url, &auth, &state, &mut shutdown_rx).await {
+            Ok(()) => return,
+            Err(e) => {
+                warn!(error = %e, ?backoff, "user-kanal tabt");
+                if e.is_throttled() {
+                    backoff = MAX_BACKOFF;
+                }
+            }
+        }
+        tokio::select! {
+            _ = &mut shutdown_rx => return,
+            _ = tokio::time::sleep(backoff) => {}
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Én forbindelse - Ok ved shutdown, Err når den tabes
+async fn run_connection(
+    url: &str,
+    auth: &ApiCredentials,
+    state: &RwLock<UserState>,
+    shutdown_rx: &mut tokio::sync::oneshot::Receiver<()>,
+) -> Result<(), Error> {
+    let (ws, _) = connect_async(url).await.map_err(|e| Error::Connect(e.to_string()))?;
+    let (mut write, mut read) = ws.split();
+
+    // Tom `markets` = alle markeder kontoen handler i
+    let subscribe = serde_json::json!({
+        "auth": {
+            "apiKey": auth.api_key,
+            "secret": auth.secret,
+            "passphrase": auth.passphrase,
+        },
+        "markets": [],
+        "type": "user",
+    });
+    write
+        .send(Message::Text(subscribe.to_string()))
+        .await
+        .map_err(|e| Error::Subscribe(format!("user-kanal: {}", e)))?;
+    info!("user-kanal forbundet");
+
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = &mut *shutdown_rx => {
+                let _ = write.send(Message::Close(None)).await;
+                return Ok(());
+            }
+            _ = ping.tick() => {
+                if let Err(e) = write.send(Message::Ping(Vec::new())).await {
+                    return Err(Error::ConnectionLost(e.to_string()));
+                }
+            }
+            msg = read.next() => {
+                let txt = match msg {
+                    None => return Err(Error::ConnectionLost("stream lukket".to_string())),
+                    Some(Err(e)) => return Err(Error::ConnectionLost(e.to_string())),
+                    Some(Ok(Message::Text(txt))) => txt,
+                    Some(Ok(Message::Close(frame))) => {
+                        let (code, reason) = frame
+                            .map(|f| (u16::from(f.code), f.reason.into_owned()))
+                            .unwrap_or((1005, String::new()));
+                        return Err(Error::ServerClosed { code, reason });
+                    }
+                    Some(Ok(_)) => continue,
+                };
+                let Ok(data) = serde_json::from_str::<serde_json::Value>(&txt) else {
+                    warn!(len = txt.len(), "ulæselig besked på user-kanalen");
+                    continue;
+                };
+                let events = match data {
+                    serde_json::Value::Array(items) => items,
+                    single => vec![single],
+                };
+                let mut state = state.write().await;
+                for event in &events {
+                    apply(event, &mut state);
+                }
+            }
+        }
+    }
+}
+
+/// Anvend ét `order`- eller `trade`-event
+fn apply(event: &serde_json::Value, state: &mut UserState) {
+    let kind = event.get("event_type").and_then(|v| v.as_str()).unwrap_or_default();
+    match kind {
+        "order" => {
+            let Some(order) = parse_order(event) else {
+                debug!("ufuldstændigt order-event");
+                return;
+            };
+            let cancelled = event.get("type").and_then(|v| v.as_str()) == Some("CANCELLATION");
+            if cancelled || order.remaining().is_zero() {
+                state.open_orders.remove(&order.id);
+            } else {
+                state.open_orders.insert(order.id.clone(), order);
+            }
+        }
+        "trade" => {
+            let Some(fill) = parse_fill(event) else {
+                debug!("ufuldstændigt trade-event");
+                return;
+            };
+            match state.fills.iter_mut().find(|f| f.id == fill.id) {
+                Some(existing) => *existing = fill,
+                None => state.fills.push(fill),
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_order(v: &serde_json::Value) -> Option<Order> {
+    Some(Order {
+        id: str_field(v, "id")?,
+        asset_id: str_field(v, "asset_id")?,
+        side: side_field(v)?,
+        price: decimal_field(v, "price")?,
+        original_size: decimal_field(v, "original_size")?,
+        size_matched: decimal_field(v, "size_matched").unwrap_or_default(),
+    })
+}
+
+fn parse_fill(v: &serde_json::Value) -> Option<Fill> {
+    Some(Fill {
+        id: str_field(v, "id")?,
+        asset_id: str_field(v, "asset_id")?,
+        side: side_field(v)?,
+        price: decimal_field(v, "price")?,
+        size: decimal_field(v, "size")?,
+        status: str_field(v, "status").unwrap_or_default(),
+    })
+}
+
+fn str_field(v: &serde_json::Value, key: &str) -> Option<String> {
+    v.get(key)?.as_str().map(str::to_string)
+}
+
+fn decimal_field(v: &serde_json::Value, key: &str) -> Option<Decimal> {
+    v.get(key)?.as_str()?.parse().ok()
+}
+
+fn side_field(v: &serde_json::Value) -> Option<OrderSide> {
+    match v.get("side")?.as_str()? {
+        s if s.eq_ignore_ascii_case("buy") => Some(OrderSide::Buy),
+        s if s.eq_ignore_ascii_case("sell") => Some(OrderSide::Sell),
+        _ => None,
+    }
+}
@@ -0,0 +1,154 @@
+//! Lokal mock af Polymarkets market-kanal og fælles hjælpere til integrationstests.
+#![allow(dead_code)]
+
+use futures_util::{SinkExt, StreamExt};
+use polymarket::orderbook::{FeedEvent, OrderbookConfig, OrderbookConfigBuilder, StateUpdated};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+pub const UP: &str = "111";
+pub const DOWN: &str = "222";
+
+/// Hvor længe en test venter på noget der skal ske
+pub const WAIT: Duration = Duration::from_secs(5);
+
+/// WebSocket-server på en tilfældig port - hver forbindelse afleveres via `accept`
+pub struct MockServer {
+    url: String,
+    conns: mpsc::UnboundedReceiver<MockConn>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, conns) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                if let Ok(ws) = tokio_tungstenite::accept_async(stream).await {
+                    let _ = tx.send(MockConn { ws });
+                }
+            }
+        });
+        Self { url, conns }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Næste forbindelse fra klienten
+    pub async fn accept(&mut self) -> MockConn {
+        tokio::time::timeout(WAIT, self.conns.recv())
+            .await
+            .expect("ingen forbindelse")
+            .expect("serveren er stoppet")
+    }
+
+    /// Næste forbindelse inden for `within`, None hvis ingen kom
+    pub async fn try_accept(&mut self, within: Duration) -> Option<MockConn> {
+        tokio::time::timeout(within, self.conns.recv()).await.ok().flatten()
+    }
+}
+
+/// Én klientforbindelse - droppes den, lukkes socket'en uden Close-frame
+pub struct MockConn {
+    ws: WebSocketStream<TcpStream>,
+}
+
+impl MockConn {
+    pub async fn send(&mut self, frame: Value) {
+        self.send_text(&frame.to_string()).await;
+    }
+
+    pub async fn send_text(&mut self, text: &str) {
+        self.ws.send(Message::Text(text.to_string())).await.unwrap();
+    }
+
+    /// Næste JSON-frame fra klienten; Ping/Pong springes over
+    pub async fn recv(&mut self) -> Value {
+        self.try_recv(WAIT).await.expect("ingen frame fra klienten")
+    }
+
+    /// Næste JSON-frame inden for `within`, None hvis ingen kom eller forbindelsen lukkede
+    pub async fn try_recv(&mut self, within: Duration) -> Option<Value> {
+        let deadline = tokio::time::Instant::now() + within;
+        loop {
+            let msg = tokio::time::timeout_at(deadline, self.ws.next()).await.ok()??.ok()?;
+            match msg {
+                Message::Text(text) => return Some(serde_json::from_str(&text).unwrap()),
+                Message::Close(_) => return None,
+                _ => continue,
+            }
+        }
+    }
+
+    /// Læs subscribe-framen og tjek dens tokens
+    pub async fn expect_subscribe(&mut self, assets: &[&str]) {
+        let frame = self.recv().await;
+        assert_eq!(frame["type"], "subscribe", "{}", frame);
+        assert_eq!(frame["assets_ids"], json!(assets), "{}", frame);
+    }
+}
+
+/// Builder mod mock-serveren: tokens `UP`/`DOWN`, ingen ping og intet reconnect-loft
+pub fn builder(url: &str) -> OrderbookConfigBuilder {
+    OrderbookConfig::builder()
+        .token_up(UP)
+        .token_down(DOWN)
+        .ws_url(url)
+        .ping_interval(None)
+        .min_reconnect_interval(Duration::ZERO)
+}
+
+pub fn config(url: &str) -> OrderbookConfig {
+    builder(url).build().unwrap()
+}
+
+pub fn levels(levels: &[(&str, &str)]) -> Vec<Value> {
+    levels.iter().map(|(price, size)| json!({ "price": price, "size": size })).collect()
+}
+
+/// `book`-snapshot for et token
+pub fn book(asset: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) -> Value {
+    json!({ "event_type": "book", "asset_id": asset, "bids": levels(bids), "asks": levels(asks) })
+}
+
+/// `price_change` med én ændring (`side` er BUY eller SELL)
+pub fn price_change(asset: &str, side: &str, price: &str, size: &str) -> Value {
+    json!({
+        "event_type": "price_change",
+        "asset_id": asset,
+        "changes": [{ "side": side, "price": price, "size": size }],
+    })
+}
+
+pub fn dec(s: &str) -> rust_decimal::Decimal {
+    s.parse().unwrap()
+}
+
+/// Næste `StateUpdated`, panik hvis intet kommer
+pub async fn next_update(rx: &mut broadcast::Receiver<StateUpdated>) -> StateUpdated {
+    tokio::time::timeout(WAIT, rx.recv()).await.expect("ingen StateUpdated").unwrap()
+}
+
+/// Intet `StateUpdated` inden for `within`
+pub async fn no_update(rx: &mut broadcast::Receiver<StateUpdated>, within: Duration) {
+    if let Ok(update) = tokio::time::timeout(within, rx.recv()).await {
+        panic!("uventet StateUpdated: {:?}", update);
+    }
+}
+
+/// Alle feed-hændelser der kommer inden for `within`
+pub async fn events_within(rx: &mut broadcast::Receiver<FeedEvent>, within: Duration) -> Vec<FeedEvent> {
+    let deadline = tokio::time::Instant::now() + within;
+    let mut seen = Vec::new();
+    while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+        seen.push(event);
+    }
+    seen
+}
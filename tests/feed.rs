@@ -0,0 +1,924 @@
+//! Orderbook-laget mod en lokal mock af market-kanalen: subscribe, bøger, deltas og reconnect.
+
+mod common;
+
+use common::*;
+use polymarket::arb::BreakerTrip;
+use polymarket::clock::MockClock;
+use polymarket::orderbook::{self, ConnectionStatus, FeedEvent, LegHealth, OverflowPolicy, Side};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+#[tokio::test]
+async fn books_and_deltas_update_state() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && update.up.ask && update.down.is_empty());
+
+    conn.send(book(DOWN, &[("0.54", "20")], &[("0.58", "30")])).await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.is_empty() && update.down.bid && update.down.ask);
+
+    let state = handle.get_current_state().await;
+    assert_eq!((state.up_bid_price, state.up_bid_size), (Some(dec("0.40")), Some(dec("100"))));
+    assert_eq!((state.up_ask_price, state.up_ask_size), (Some(dec("0.45")), Some(dec("50"))));
+    assert_eq!((state.down_bid_price, state.down_bid_size), (Some(dec("0.54")), Some(dec("20"))));
+    assert_eq!((state.down_ask_price, state.down_ask_size), (Some(dec("0.58")), Some(dec("30"))));
+    assert_eq!(handle.status(), ConnectionStatus::BooksLive);
+
+    // Et bedre bid via delta flytter kun UP bid
+    conn.send(price_change(UP, "BUY", "0.42", "10")).await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && !update.up.ask && update.down.is_empty());
+    let state = handle.get_current_state().await;
+    assert_eq!((state.up_bid_price, state.up_bid_size), (Some(dec("0.42")), Some(dec("10"))));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn unsorted_levels_pick_the_best_price() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // Ingen bestemt rækkefølge, og et niveau med size 0 er fjernet
+    let bids = [("0.30", "5"), ("0.45", "7"), ("0.48", "0"), ("0.40", "9")];
+    let asks = [("0.60", "1"), ("0.50", "2"), ("0.49", "0"), ("0.55", "3")];
+    conn.send(book(UP, &bids, &asks)).await;
+    next_update(&mut updates).await;
+
+    let state = handle.get_current_state().await;
+    assert_eq!((state.up_bid_price, state.up_bid_size), (Some(dec("0.45")), Some(dec("7"))));
+    assert_eq!((state.up_ask_price, state.up_ask_size), (Some(dec("0.50")), Some(dec("2"))));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn empty_updates_are_ignored_and_emptied_sides_cleared() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    next_update(&mut updates).await;
+
+    // En delta uden ændringer giver intet signal
+    conn.send(json!({ "event_type": "price_change", "asset_id": UP, "changes": [] })).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+    assert_eq!(handle.get_current_state().await.up_bid_price, Some(dec("0.40")));
+
+    // En delta der fjerner sidste bid tømmer siden
+    conn.send(price_change(UP, "BUY", "0.40", "0")).await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && !update.up.ask);
+    let state = handle.get_current_state().await;
+    assert_eq!((state.up_bid_price, state.up_bid_size), (None, None));
+    assert_eq!(state.up_ask_price, Some(dec("0.45")));
+
+    // Et snapshot uden niveauer beholder den kendte state
+    conn.send(book(UP, &[], &[])).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+    assert_eq!(handle.get_current_state().await.up_ask_price, Some(dec("0.45")));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn reconnects_and_resubscribes_after_a_dropped_socket() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut status = handle.subscribe_status();
+    let mut events = handle.subscribe_events();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    conn.send(book(DOWN, &[("0.54", "20")], &[("0.58", "30")])).await;
+    next_update(&mut updates).await;
+    next_update(&mut updates).await;
+
+    drop(conn);
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    let mut seen = Vec::new();
+    while let Ok(Ok(s)) = tokio::time::timeout(Duration::from_millis(200), status.recv()).await {
+        seen.push(s);
+    }
+    let lost = seen.iter().position(|s| *s == ConnectionStatus::Disconnected).expect("Disconnected");
+    let reconnecting = seen.iter().position(|s| *s == ConnectionStatus::Reconnecting).expect("Reconnecting");
+    let connected = seen.iter().rposition(|s| *s == ConnectionStatus::Connected).expect("Connected");
+    assert!(lost < reconnecting && reconnecting < connected, "{:?}", seen);
+
+    let mut lost_at = None;
+    let mut connected_at = None;
+    while let Ok(Ok(event)) = tokio::time::timeout(Duration::from_millis(200), events.recv()).await {
+        match event {
+            FeedEvent::Disconnected { at_ms, .. } => lost_at = Some(at_ms),
+            FeedEvent::Connected { at_ms } if lost_at.is_some() => connected_at = Some(at_ms),
+            _ => {}
+        }
+    }
+    assert!(lost_at.unwrap() <= connected_at.unwrap());
+
+    // Den nye forbindelses bog erstatter den gamle
+    conn.send(book(UP, &[("0.41", "10")], &[("0.44", "5")])).await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && update.up.ask);
+    assert_eq!(handle.get_current_state().await.up_bid_price, Some(dec("0.41")));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn empty_book_resubscribes_only_that_token() {
+    let mut server = MockServer::start().await;
+    let config = builder(server.url()).empty_book_resync(Some(Duration::from_millis(100))).build().unwrap();
+    let handle = orderbook::spawn(config);
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    conn.send(book(DOWN, &[("0.54", "20")], &[("0.58", "30")])).await;
+    next_update(&mut updates).await;
+    next_update(&mut updates).await;
+
+    // UP tømmes og forbliver tomt - kun UP afmeldes og abonneres igen
+    conn.send(price_change(UP, "BUY", "0.40", "0")).await;
+    conn.send(price_change(UP, "SELL", "0.45", "0")).await;
+    let frame = conn.recv().await;
+    assert_eq!((&frame["type"], &frame["assets_ids"]), (&json!("unsubscribe"), &json!([UP])), "{}", frame);
+    conn.expect_subscribe(&[UP]).await;
+    assert_eq!(handle.stats().resyncs.total, 1);
+    assert_eq!(handle.stats().reconnects.total, 0);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn empty_book_resync_is_off_by_default() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(book(UP, &[("0.40", "100")], &[])).await;
+    next_update(&mut updates).await;
+    conn.send(price_change(UP, "BUY", "0.40", "0")).await;
+    next_update(&mut updates).await;
+    assert_eq!(conn.try_recv(Duration::from_millis(300)).await, None);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn garbage_frames_are_counted_and_valid_ones_still_apply() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut events = handle.subscribe_events();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send_text("not json").await;
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    next_update(&mut updates).await;
+    conn.send_text("{\"event_type\": \"book\"").await;
+    conn.send_text("").await;
+    conn.send(price_change(UP, "BUY", "0.42", "10")).await;
+    next_update(&mut updates).await;
+
+    assert_eq!(handle.stats().parse_failures.total, 3);
+    assert_eq!(handle.get_current_state().await.up_bid_price, Some(dec("0.42")));
+    // Uden en tærskel tælles der kun
+    while let Ok(event) = events.try_recv() {
+        assert!(!matches!(event, FeedEvent::ParseFailureSpike { .. }), "{:?}", event);
+    }
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn parse_failure_spike_is_reported_once_above_the_rate() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(builder(server.url()).parse_failure_alert_rate(Some(0.01)).build().unwrap());
+    let mut updates = handle.subscribe_updates();
+    let mut events = handle.subscribe_events();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    for _ in 0..3 {
+        conn.send_text("garbage").await;
+    }
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    next_update(&mut updates).await;
+
+    let mut spikes = 0;
+    while let Ok(event) = events.try_recv() {
+        if let FeedEvent::ParseFailureSpike { per_minute } = event {
+            assert!(per_minute > 0);
+            spikes += 1;
+        }
+    }
+    assert_eq!(spikes, 1);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn failed_rest_resync_falls_back_to_resubscribe() {
+    let mut server = MockServer::start().await;
+    // Ingen lytter på porten - hvert REST-snapshot fejler
+    let rest = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let rest_url = format!("http://{}/book?token_id=", rest.local_addr().unwrap());
+    drop(rest);
+    let config = builder(server.url())
+        .seed_client(reqwest::Client::new())
+        .rest_book_url(rest_url)
+        .build()
+        .unwrap();
+    let handle = orderbook::spawn(config);
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // En delta uden snapshot bryder kæden; REST fejler, så DOWN abonneres igen over WS
+    conn.send(price_change(DOWN, "BUY", "0.54", "20")).await;
+    let frame = conn.recv().await;
+    assert_eq!((&frame["type"], &frame["assets_ids"]), (&json!("unsubscribe"), &json!([DOWN])), "{}", frame);
+    conn.expect_subscribe(&[DOWN]).await;
+
+    // Snapshottet fra WS genopretter kæden, og deltas anvendes igen
+    conn.send(book(DOWN, &[("0.54", "20")], &[("0.58", "30")])).await;
+    next_update(&mut updates).await;
+    conn.send(price_change(DOWN, "BUY", "0.55", "5")).await;
+    next_update(&mut updates).await;
+    assert_eq!(handle.get_current_state().await.down_bid_price, Some(dec("0.55")));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn arb_signals_are_written_to_the_audit_log() {
+    let path = std::env::temp_dir().join(format!("feed-audit-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(builder(server.url()).audit_path(&path).build().unwrap());
+    let mut events = handle.subscribe_events();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // 0.45 + 0.52 < 1 - køb begge ben
+    conn.send(book(UP, &[("0.44", "10")], &[("0.45", "10")])).await;
+    conn.send(book(DOWN, &[("0.51", "10")], &[("0.52", "4")])).await;
+    let opportunity = loop {
+        match tokio::time::timeout(WAIT, events.recv()).await.expect("intet ArbDetected").unwrap() {
+            FeedEvent::ArbDetected(opportunity) => break opportunity,
+            _ => continue,
+        }
+    };
+
+    // Linjen er skrevet før signalet blev sendt
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<serde_json::Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    let line = &lines[0];
+    assert_eq!((&line["token_up"], &line["token_down"]), (&json!(UP), &json!(DOWN)));
+    assert_eq!(line["kind"], "buy_both");
+    assert_eq!(line["edge_cents"], json!(opportunity.edge_cents.to_string()));
+    assert_eq!((&line["up_price"], &line["down_price"]), (&json!("0.45"), &json!("0.52")));
+    assert_eq!(line["size"], "4");
+    assert_eq!(line["status"], "books_live");
+    assert!(line["ts_ms"].as_i64().unwrap() > 0 && line["age_ms"].is_i64());
+
+    handle.shutdown().await;
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn budget_sizing_uses_only_real_depth() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(builder(server.url()).derive_complement(true).build().unwrap());
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // DOWN ask 0.60 er kun afledt af UP bid - intet at købe
+    conn.send(book(UP, &[("0.40", "10")], &[("0.45", "5"), ("0.47", "10")])).await;
+    next_update(&mut updates).await;
+    assert_eq!(handle.get_current_state().await.down_ask_price, Some(dec("0.60")));
+    assert_eq!(handle.max_pairs_for_budget(dec("100"), dec("0")).await, (dec("0"), dec("0")));
+
+    conn.send(book(DOWN, &[], &[("0.50", "3"), ("0.51", "20")])).await;
+    next_update(&mut updates).await;
+    assert_eq!(handle.max_pairs_for_budget(dec("100"), dec("0")).await, (dec("15"), dec("0.43")));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn rapid_updates_within_one_interval_are_coalesced() {
+    let mut server = MockServer::start().await;
+    let interval = Duration::from_millis(500);
+    let handle = orderbook::spawn(builder(server.url()).coalesce_interval(interval).build().unwrap());
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    for (i, price) in ["0.41", "0.42", "0.43", "0.44"].into_iter().enumerate() {
+        conn.send(price_change(UP, "BUY", price, &(i + 1).to_string())).await;
+    }
+
+    // Ét signal med alle ændringer, og den nyeste state
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && update.up.ask && update.down.is_empty());
+    let state = handle.get_current_state().await;
+    assert_eq!((state.up_bid_price, state.up_bid_size), (Some(dec("0.44")), Some(dec("4"))));
+    no_update(&mut updates, interval + Duration::from_millis(200)).await;
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn breaker_age_follows_the_last_message_on_each_leg() {
+    let mut server = MockServer::start().await;
+    let clock = MockClock::new(1_700_000_000_000);
+    let handle = orderbook::spawn(builder(server.url()).clock(Arc::new(clock.clone())).build().unwrap());
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    let up = book(UP, &[("0.40", "10")], &[("0.42", "10")]);
+    let down = book(DOWN, &[("0.57", "10")], &[("0.59", "10")]);
+    conn.send(up.clone()).await;
+    conn.send(down.clone()).await;
+    next_update(&mut updates).await;
+    next_update(&mut updates).await;
+    assert_eq!(handle.breaker_state(), None);
+
+    // Samme bøger igen efter 61 s - priserne står stille, men begge ben lever
+    clock.advance(Duration::from_secs(61));
+    conn.send(up.clone()).await;
+    conn.send(down).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+    assert_eq!(handle.breaker_state(), None);
+
+    // Kun UP svarer - DOWN er nu forældet
+    clock.advance(Duration::from_secs(61));
+    conn.send(up).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+    assert_eq!(handle.breaker_state(), Some(BreakerTrip::StaleData { age_ms: 61_000 }));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn a_wide_spread_leg_suppresses_arb_signals() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(builder(server.url()).max_spread(dec("0.10")).build().unwrap());
+    let mut updates = handle.subscribe_updates();
+    let mut events = handle.subscribe_events();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // 0.45 + 0.52 < 1, men UP-spread er 0.35 - vises, men signaleres ikke
+    conn.send(book(UP, &[("0.10", "10")], &[("0.45", "10")])).await;
+    conn.send(book(DOWN, &[("0.50", "10")], &[("0.52", "10")])).await;
+    next_update(&mut updates).await;
+    next_update(&mut updates).await;
+    let state = handle.get_current_state().await;
+    assert_eq!(state.up_ask_price, Some(dec("0.45")));
+    assert!(state.up_wide_spread && !state.down_wide_spread);
+    let seen = events_within(&mut events, Duration::from_millis(200)).await;
+    assert!(!seen.iter().any(|e| matches!(e, FeedEvent::ArbDetected(_))), "{:?}", seen);
+
+    // Strammes spreaden ind, er benet troværdigt igen
+    conn.send(price_change(UP, "BUY", "0.40", "10")).await;
+    next_update(&mut updates).await;
+    assert!(!handle.get_current_state().await.up_wide_spread);
+    let seen = events_within(&mut events, Duration::from_millis(200)).await;
+    assert!(seen.iter().any(|e| matches!(e, FeedEvent::ArbDetected(_))), "{:?}", seen);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn each_leg_is_reported_ready_once_per_connection() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut events = handle.subscribe_events();
+    let ready = |seen: &[FeedEvent]| -> Vec<_> {
+        seen.iter()
+            .filter_map(|e| match e {
+                FeedEvent::LegReady { side } => Some(*side),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // Anden runde er efter reconnect (forbindelsen droppes) - begge ben meldes igen
+    for _ in 0..2 {
+        let mut conn = server.accept().await;
+        conn.expect_subscribe(&[UP, DOWN]).await;
+        conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+        next_update(&mut updates).await;
+        conn.send(price_change(UP, "BUY", "0.41", "10")).await;
+        next_update(&mut updates).await;
+        assert_eq!(ready(&events_within(&mut events, Duration::from_millis(200)).await), [Side::Up]);
+
+        conn.send(book(DOWN, &[("0.54", "20")], &[("0.58", "30")])).await;
+        conn.send(price_change(DOWN, "SELL", "0.57", "5")).await;
+        next_update(&mut updates).await;
+        next_update(&mut updates).await;
+        assert_eq!(ready(&events_within(&mut events, Duration::from_millis(200)).await), [Side::Down]);
+    }
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn a_leg_is_disabled_after_bad_messages_and_recovers_after_cooldown() {
+    let mut server = MockServer::start().await;
+    let clock = MockClock::new(1_700_000_000_000);
+    let config = builder(server.url())
+        .clock(Arc::new(clock.clone()))
+        .max_bad_messages(2)
+        .leg_cooldown(Duration::from_secs(30))
+        .build()
+        .unwrap();
+    let handle = orderbook::spawn(config);
+    let mut updates = handle.subscribe_updates();
+    let mut events = handle.subscribe_events();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(book(UP, &[("0.40", "10")], &[("0.45", "10")])).await;
+    next_update(&mut updates).await;
+
+    // En krydset bog og en ugyldig pris i træk - benet slås fra, den kendte bog består
+    conn.send(book(UP, &[("0.50", "10")], &[("0.45", "10")])).await;
+    conn.send(price_change(UP, "BUY", "1.5", "10")).await;
+    let seen = events_within(&mut events, Duration::from_millis(200)).await;
+    assert!(seen.iter().any(|e| matches!(e, FeedEvent::LegDisabled { side: Side::Up })), "{:?}", seen);
+    let state = handle.get_current_state().await;
+    assert_eq!(state.up_health, LegHealth::Disabled { until_ms: 1_700_000_030_000 });
+    assert_eq!(state.up_bid_price, Some(dec("0.40")));
+
+    // Gyldig data i cooldown ignoreres
+    conn.send(price_change(UP, "BUY", "0.41", "10")).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+
+    // Efter cooldown tages benet i brug igen
+    clock.advance(Duration::from_secs(31));
+    conn.send(price_change(UP, "BUY", "0.42", "10")).await;
+    next_update(&mut updates).await;
+    let seen = events_within(&mut events, Duration::from_millis(200)).await;
+    assert!(seen.iter().any(|e| matches!(e, FeedEvent::LegEnabled { side: Side::Up })), "{:?}", seen);
+    let state = handle.get_current_state().await;
+    assert_eq!(state.up_health, LegHealth::Healthy);
+    assert_eq!(state.up_bid_price, Some(dec("0.42")));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn a_sequence_gap_resubscribes_that_asset() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    let with_seq = |mut frame: serde_json::Value, seq: u64| {
+        frame["seq"] = json!(seq);
+        frame
+    };
+
+    conn.send(with_seq(book(UP, &[("0.40", "100")], &[("0.45", "50")]), 1)).await;
+    conn.send(with_seq(book(DOWN, &[("0.54", "20")], &[("0.58", "30")]), 7)).await;
+    conn.send(with_seq(price_change(UP, "BUY", "0.41", "10"), 2)).await;
+    conn.send(with_seq(price_change(DOWN, "BUY", "0.55", "10"), 8)).await;
+    for _ in 0..4 {
+        next_update(&mut updates).await;
+    }
+    assert_eq!(conn.try_recv(Duration::from_millis(200)).await, None);
+
+    // UP springer 3 og 4 over - kun UP hentes igen
+    conn.send(with_seq(price_change(UP, "BUY", "0.42", "10"), 5)).await;
+    let frame = conn.recv().await;
+    assert_eq!((&frame["type"], &frame["assets_ids"]), (&json!("unsubscribe"), &json!([UP])), "{}", frame);
+    conn.expect_subscribe(&[UP]).await;
+    assert_eq!(handle.stats().resyncs.total, 1);
+
+    handle.shutdown().await;
+}
+
+/// Næste `Connected`/`Disconnected` - øvrige hændelser springes over
+async fn next_link_event(events: &mut broadcast::Receiver<FeedEvent>) -> FeedEvent {
+    loop {
+        match tokio::time::timeout(WAIT, events.recv()).await.expect("ingen forbindelseshændelse").unwrap() {
+            event @ (FeedEvent::Connected { .. } | FeedEvent::Disconnected { .. }) => return event,
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn disconnect_is_reported_before_backoff_and_the_gap_is_measurable() {
+    let mut server = MockServer::start().await;
+    let start_ms = 1_700_000_000_000;
+    let clock = MockClock::new(start_ms);
+    let handle = orderbook::spawn(builder(server.url()).clock(Arc::new(clock.clone())).build().unwrap());
+    let mut events = handle.subscribe_events();
+    let conn = server.accept().await;
+    assert!(matches!(next_link_event(&mut events).await, FeedEvent::Connected { at_ms } if at_ms == start_ms));
+
+    // Tabet meldes med det samme - uret flyttes mens klienten venter på backoff
+    clock.advance(Duration::from_secs(5));
+    drop(conn);
+    match next_link_event(&mut events).await {
+        FeedEvent::Disconnected { at_ms, reason } => {
+            assert_eq!(at_ms, start_ms + 5_000);
+            assert!(!reason.is_empty());
+        }
+        event => panic!("forventede Disconnected: {:?}", event),
+    }
+    clock.advance(Duration::from_secs(2));
+
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    match next_link_event(&mut events).await {
+        FeedEvent::Connected { at_ms } => assert_eq!(at_ms - (start_ms + 5_000), 2_000),
+        event => panic!("forventede Connected: {:?}", event),
+    }
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn rapid_failures_still_respect_the_minimum_reconnect_interval() {
+    let mut server = MockServer::start().await;
+    let floor = Duration::from_millis(1500);
+    let handle = orderbook::spawn(builder(server.url()).min_reconnect_interval(floor).build().unwrap());
+
+    // Hver forbindelse lukkes straks - backoff alene (500 ms) ville forbinde hurtigere
+    let mut accepted = Vec::new();
+    for _ in 0..3 {
+        drop(server.accept().await);
+        accepted.push(tokio::time::Instant::now());
+    }
+    for pair in accepted.windows(2) {
+        let gap = pair[1] - pair[0];
+        assert!(gap >= floor - Duration::from_millis(100), "{:?}", gap);
+    }
+    assert!(handle.stats().reconnect_floor_delays.total >= 2);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn a_batched_frame_updating_both_legs_signals_once() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    conn.send(json!([
+        book(UP, &[("0.40", "100")], &[("0.45", "50")]),
+        book(DOWN, &[("0.54", "20")], &[("0.58", "30")]),
+    ]))
+    .await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && update.up.ask && update.down.bid && update.down.ask);
+    no_update(&mut updates, Duration::from_millis(200)).await;
+
+    // price_changes på tværs af begge ben i én frame - også ét signal
+    conn.send(json!({
+        "event_type": "price_change",
+        "price_changes": [
+            { "asset_id": UP, "side": "BUY", "price": "0.41", "size": "10" },
+            { "asset_id": DOWN, "side": "SELL", "price": "0.57", "size": "5" },
+        ],
+    }))
+    .await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && !update.up.ask && !update.down.bid && update.down.ask);
+    no_update(&mut updates, Duration::from_millis(200)).await;
+    let state = handle.get_current_state().await;
+    assert_eq!((state.up_bid_price, state.down_ask_price), (Some(dec("0.41")), Some(dec("0.57"))));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn periodic_resync_resubscribes_on_the_configured_cadence() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(builder(server.url()).periodic_resync_secs(1).build().unwrap());
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    let subscribed = tokio::time::Instant::now();
+
+    for round in 1..=2u32 {
+        // Intet før perioden er gået
+        assert_eq!(conn.try_recv(Duration::from_millis(800)).await, None);
+        for asset in [UP, DOWN] {
+            let frame = conn.recv().await;
+            assert_eq!((&frame["type"], &frame["assets_ids"]), (&json!("unsubscribe"), &json!([asset])), "{}", frame);
+            conn.expect_subscribe(&[asset]).await;
+        }
+        let elapsed = subscribed.elapsed();
+        assert!(elapsed >= Duration::from_secs(round.into()) - Duration::from_millis(50), "{:?}", elapsed);
+        // Tælles som en resync ligesom de andre veje
+        assert_eq!(handle.stats().resyncs.total, u64::from(round));
+    }
+    // Forbindelsen beholdes
+    assert!(server.try_accept(Duration::from_millis(100)).await.is_none());
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn dropping_the_handle_stops_the_task_and_closes_the_socket() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut status = handle.subscribe_status();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // Samme pæne nedlukning som `shutdown`: afmeld, og luk forbindelsen
+    drop(handle);
+    let closed = tokio::time::Instant::now();
+    assert_eq!(conn.recv().await["type"], "unsubscribe");
+    assert_eq!(conn.try_recv(WAIT).await, None);
+    assert!(closed.elapsed() < WAIT, "socket'en blev ikke lukket");
+
+    // Tasken er væk: status-kanalen lukkes, og der forbindes ikke igen
+    let closed = loop {
+        match tokio::time::timeout(WAIT, status.recv()).await.expect("status-kanalen lever stadig") {
+            Ok(_) => continue,
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(closed, broadcast::error::RecvError::Closed);
+    assert!(server.try_accept(Duration::from_millis(800)).await.is_none());
+}
+
+#[tokio::test]
+async fn unchanged_top_of_book_is_not_signalled_again() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    let snapshot = book(UP, &[("0.40", "100"), ("0.39", "5")], &[("0.45", "50")]);
+    conn.send(snapshot.clone()).await;
+    conn.send(snapshot).await;
+    next_update(&mut updates).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+
+    // Et dybere niveau ændres - toppen står stille
+    conn.send(price_change(UP, "BUY", "0.39", "50")).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+
+    // Size på toppen er en ændring
+    conn.send(price_change(UP, "BUY", "0.40", "90")).await;
+    let update = next_update(&mut updates).await;
+    assert!(update.up.bid && !update.up.ask && update.down.is_empty());
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn subscriptions_are_batched_and_routing_ignores_the_batching() {
+    let mut server = MockServer::start().await;
+    let tokens = [(UP, DOWN), ("333", "444"), ("555", "666")];
+    let configs = tokens
+        .iter()
+        .map(|(up, down)| builder(server.url()).token_up(*up).token_down(*down).max_assets_per_frame(4).build().unwrap())
+        .collect();
+    let multi = orderbook::spawn_multi(configs).unwrap();
+
+    // Samme opdeling ved første forbindelse og efter reconnect
+    let mut conn = server.accept().await;
+    for reconnect in [false, true] {
+        if reconnect {
+            drop(conn);
+            conn = server.accept().await;
+        }
+        conn.expect_subscribe(&[UP, DOWN, "333", "444"]).await;
+        conn.expect_subscribe(&["555", "666"]).await;
+        assert_eq!(conn.try_recv(Duration::from_millis(200)).await, None);
+    }
+
+    // Bøger fra forskellige frames lander hos hvert sit marked
+    let mut updates: Vec<_> = (0..3).map(|i| multi.market(i).unwrap().subscribe_updates()).collect();
+    conn.send(json!([book("444", &[("0.30", "1")], &[]), book(UP, &[("0.40", "1")], &[])])).await;
+    conn.send(book("555", &[("0.50", "1")], &[])).await;
+    for rx in &mut updates {
+        next_update(rx).await;
+    }
+    assert_eq!(multi.get_current_state(0).await.unwrap().up_bid_price, Some(dec("0.40")));
+    assert_eq!(multi.get_current_state(1).await.unwrap().down_bid_price, Some(dec("0.30")));
+    assert_eq!(multi.get_current_state(2).await.unwrap().up_bid_price, Some(dec("0.50")));
+    assert_eq!(multi.market_by_token("666").unwrap().get_current_state().await.up_bid_price, Some(dec("0.50")));
+
+    multi.shutdown().await;
+}
+
+#[tokio::test]
+async fn manual_resync_resubscribes_and_rebuilds_from_the_next_snapshot() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    conn.send(book(UP, &[("0.40", "10"), ("0.39", "10")], &[("0.45", "10")])).await;
+    next_update(&mut updates).await;
+
+    // Kan kaldes gentagne gange - hver gang afmeldes og subscribes begge tokens
+    for _ in 0..2 {
+        handle.resync().await;
+        for asset in [UP, DOWN] {
+            let frame = conn.recv().await;
+            assert_eq!((&frame["type"], &frame["assets_ids"]), (&json!("unsubscribe"), &json!([asset])), "{}", frame);
+            conn.expect_subscribe(&[asset]).await;
+        }
+    }
+    assert_eq!(handle.stats().resyncs.total, 2);
+
+    // Deltas før snapshottet ignoreres; snapshottet bygger bogen forfra uden de gamle niveauer
+    conn.send(price_change(UP, "BUY", "0.41", "10")).await;
+    no_update(&mut updates, Duration::from_millis(200)).await;
+    conn.send(book(UP, &[("0.35", "10")], &[("0.45", "10")])).await;
+    next_update(&mut updates).await;
+    conn.send(price_change(UP, "BUY", "0.35", "0")).await;
+    next_update(&mut updates).await;
+    assert_eq!(handle.get_current_state().await.up_bid_price, None);
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn max_update_hz_caps_the_emit_rate_and_delivers_the_last_state() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(builder(server.url()).max_update_hz(5).build().unwrap());
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // 50 ændringer over ca. 1 s - langt hurtigere end 5 Hz
+    let started = tokio::time::Instant::now();
+    conn.send(book(UP, &[("0.10", "1")], &[("0.95", "1")])).await;
+    for i in 1..=50 {
+        conn.send(price_change(UP, "BUY", &format!("0.{}", 10 + i), "1")).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let mut received = 0;
+    while tokio::time::timeout(Duration::from_millis(500), updates.recv()).await.is_ok() {
+        received += 1;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    // Højst én pr. 200 ms plus den første og den afsluttende flush
+    assert!(received >= 2 && (received as f64) <= elapsed * 5.0 + 2.0, "{} på {:.2} s", received, elapsed);
+    assert_eq!(handle.get_current_state().await.up_bid_price, Some(dec("0.60")));
+    assert_eq!(handle.latest().up_bid_price, Some(dec("0.60")));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn a_full_reliable_queue_follows_its_overflow_policy() {
+    let mut server = MockServer::start().await;
+    let handle = orderbook::spawn(config(server.url()));
+    let mut updates = handle.subscribe_updates();
+    let mut keep = handle.subscribe_reliable(2, OverflowPolicy::DropNewest);
+    let mut close = handle.subscribe_reliable(2, OverflowPolicy::Close);
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+
+    // Fem snapshots i en kø på to, uden at nogen læser
+    conn.send(book(UP, &[("0.40", "1")], &[("0.60", "1")])).await;
+    next_update(&mut updates).await;
+    for price in ["0.41", "0.42", "0.43", "0.44"] {
+        conn.send(price_change(UP, "BUY", price, "1")).await;
+        next_update(&mut updates).await;
+    }
+
+    // DropNewest beholder de ældste og tæller resten
+    assert_eq!(keep.overflowed(), 3);
+    assert_eq!(keep.recv().await.unwrap().up_bid_price, Some(dec("0.40")));
+    assert_eq!(keep.recv().await.unwrap().up_bid_price, Some(dec("0.41")));
+    assert!(tokio::time::timeout(Duration::from_millis(200), keep.recv()).await.is_err());
+
+    // Close leverer det der nåede i køen og slutter så strømmen
+    assert_eq!(close.overflowed(), 1);
+    assert_eq!(close.recv().await.unwrap().up_bid_price, Some(dec("0.40")));
+    assert_eq!(close.recv().await.unwrap().up_bid_price, Some(dec("0.41")));
+    assert!(close.recv().await.is_none());
+
+    // DropNewest får igen nye snapshots når der er plads
+    conn.send(price_change(UP, "BUY", "0.45", "1")).await;
+    next_update(&mut updates).await;
+    assert_eq!(keep.recv().await.unwrap().up_bid_price, Some(dec("0.45")));
+
+    handle.shutdown().await;
+}
+
+#[tokio::test]
+async fn markets_are_ranked_by_edge_without_tripped_ones() {
+    let mut server = MockServer::start().await;
+    let tokens = [(UP, DOWN), ("333", "444"), ("555", "666"), ("777", "888")];
+    let configs = tokens
+        .iter()
+        .map(|(up, down)| builder(server.url()).token_up(*up).token_down(*down).build().unwrap())
+        .collect();
+    let multi = orderbook::spawn_multi(configs).unwrap();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN, "333", "444", "555", "666", "777", "888"]).await;
+    assert!(multi.ranked_opportunities().is_empty());
+
+    // 2c, 4c, 15c (over breakerens 10c) og intet arb
+    let books = [
+        (UP, DOWN, ("0.47", "0.48", "5"), ("0.49", "0.50", "5")),
+        ("333", "444", ("0.45", "0.46", "3"), ("0.49", "0.50", "3")),
+        ("555", "666", ("0.39", "0.40", "9"), ("0.44", "0.45", "9")),
+        ("777", "888", ("0.49", "0.50", "9"), ("0.49", "0.50", "9")),
+    ];
+    let mut updates: Vec<_> = (0..4).map(|i| multi.market(i).unwrap().subscribe_updates()).collect();
+    for (i, (up, down, (up_bid, up_ask, up_size), (down_bid, down_ask, down_size))) in books.into_iter().enumerate() {
+        conn.send(book(up, &[(up_bid, up_size)], &[(up_ask, up_size)])).await;
+        next_update(&mut updates[i]).await;
+        conn.send(book(down, &[(down_bid, down_size)], &[(down_ask, down_size)])).await;
+        next_update(&mut updates[i]).await;
+    }
+
+    assert_eq!(multi.market(2).unwrap().breaker_state(), Some(BreakerTrip::ImplausibleEdge { edge_cents: dec("15.00") }));
+    assert_eq!(multi.ranked_opportunities(), vec![(1, 4.0, dec("3")), (0, 2.0, dec("5"))]);
+
+    multi.shutdown().await;
+}
+
+#[tokio::test]
+async fn fill_time_follows_the_recent_volume_at_or_through_the_price() {
+    let mut server = MockServer::start().await;
+    let clock = MockClock::new(1_700_000_000_000);
+    let config = builder(server.url())
+        .clock(Arc::new(clock.clone()))
+        .track_trades(true)
+        .trade_window(Duration::from_secs(60))
+        .build()
+        .unwrap();
+    let handle = orderbook::spawn(config);
+    let mut updates = handle.subscribe_updates();
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    assert_eq!(conn.recv().await["channel"], "last_trade_price");
+    let trade = |price: &str, size: &str| json!({ "event_type": "last_trade_price", "asset_id": UP, "price": price, "size": size });
+
+    // Ingen handler endnu
+    assert_eq!(handle.estimated_fill_time(Side::Up, dec("0.40"), dec("30")).await, None);
+
+    // 10 shares hvert 10. sekund til 0.40 = 1 share/s over vinduet, plus 100 over prisen
+    for _ in 0..6 {
+        conn.send(trade("0.40", "10")).await;
+        clock.advance(Duration::from_secs(10));
+    }
+    conn.send(trade("0.45", "100")).await;
+    // Handler giver ingen update - en bog bagefter viser at de er behandlet
+    conn.send(book(UP, &[("0.40", "10")], &[("0.45", "10")])).await;
+    next_update(&mut updates).await;
+
+    assert_eq!(handle.estimated_fill_time(Side::Up, dec("0.40"), dec("30")).await, Some(Duration::from_secs(30)));
+    // Et højere bid rammes også af handlerne over 0.40
+    assert_eq!(handle.estimated_fill_time(Side::Up, dec("0.50"), dec("80")).await, Some(Duration::from_secs(30)));
+    // Under alle handlede priser, eller på det andet ben, er der intet at regne på
+    assert_eq!(handle.estimated_fill_time(Side::Up, dec("0.35"), dec("30")).await, None);
+    assert_eq!(handle.estimated_fill_time(Side::Down, dec("0.40"), dec("30")).await, None);
+
+    // Handler ældre end vinduet tæller ikke
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(handle.estimated_fill_time(Side::Up, dec("0.50"), dec("30")).await, None);
+
+    handle.shutdown().await;
+}
@@ -0,0 +1,233 @@
+//! Sessionsløkken mod en lokal mock: udløb styret af `MockClock` og rollover til næste slot.
+
+mod common;
+
+use common::*;
+use polymarket::clock::MockClock;
+use polymarket::market::{DiscoveryError, Market};
+use polymarket::session::{self, ManagerEvent, MarketManager, SessionConfig, SessionEvent};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const START_MS: i64 = 1_700_000_000_000;
+
+fn market(slug: &str, end_ts: i64, token_up: &str, token_down: &str) -> Market {
+    Market {
+        slug: slug.to_string(),
+        title: slug.to_string(),
+        end_ts,
+        token_up: token_up.to_string(),
+        token_down: token_down.to_string(),
+        labels: Default::default(),
+        outcomes: Vec::new(),
+        tick_size: dec("0.01"),
+    }
+}
+
+/// Kort beskrivelse af et event, så testen kan sammenligne rækkefølgen
+fn describe(event: &SessionEvent<'_>) -> Option<String> {
+    match event {
+        SessionEvent::Started(m) => Some(format!("started {}", m.slug)),
+        SessionEvent::Expired(m) => Some(format!("expired {}", m.slug)),
+        SessionEvent::Ended(m) => Some(format!("ended {}", m.slug)),
+        SessionEvent::NoData(m) => Some(format!("no data {}", m.slug)),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn rolls_over_when_the_clock_passes_end_ts() {
+    let mut server = MockServer::start().await;
+    let clock = MockClock::new(START_MS);
+    let end_ts = START_MS / 1000 + 60;
+    let config = SessionConfig {
+        orderbook: builder(server.url()).clock(Arc::new(clock.clone())).build().unwrap(),
+        first_data_deadline: None,
+        retry_delay: Duration::from_millis(10),
+        ..Default::default()
+    };
+    let mut markets = vec![market("slot-1", end_ts, UP, DOWN), market("slot-2", end_ts + 900, "333", "444")].into_iter();
+    let discover = move || {
+        let next = markets.next();
+        async move { next.ok_or(DiscoveryError::NoActiveSlot { tried: Vec::new() }) }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let session = tokio::spawn(async move {
+        session::run_session(discover, &config, |event| {
+            let Some(line) = describe(&event) else { return ControlFlow::Continue(()) };
+            let last = line == "started slot-2";
+            let _ = tx.send(line);
+            if last { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        })
+        .await;
+    });
+
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    assert_eq!(rx.recv().await.unwrap(), "started slot-1");
+
+    // Uret står stille - intet udløb før det flyttes forbi end_ts
+    assert!(tokio::time::timeout(Duration::from_millis(1500), rx.recv()).await.is_err());
+    clock.advance(Duration::from_secs(61));
+
+    let mut seen = Vec::new();
+    while let Ok(Some(line)) = tokio::time::timeout(WAIT, rx.recv()).await {
+        seen.push(line);
+    }
+    assert_eq!(seen, ["expired slot-1", "ended slot-1", "started slot-2"]);
+    tokio::time::timeout(WAIT, session).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn observation_continues_for_the_window_after_expiry() {
+    let mut server = MockServer::start().await;
+    let clock = MockClock::new(START_MS);
+    let end_ts = START_MS / 1000 + 60;
+    let config = SessionConfig {
+        orderbook: builder(server.url()).clock(Arc::new(clock.clone())).build().unwrap(),
+        first_data_deadline: None,
+        post_expiry_observe_secs: 30,
+        ..Default::default()
+    };
+    let mut markets = vec![market("slot-1", end_ts, UP, DOWN)].into_iter();
+    let discover = move || {
+        let next = markets.next();
+        async move { next.ok_or(DiscoveryError::NoActiveSlot { tried: Vec::new() }) }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let session = tokio::spawn(async move {
+        session::run_session(discover, &config, |event| {
+            let line = match &event {
+                SessionEvent::Update { expired: true, .. } => "expired update".to_string(),
+                _ => match describe(&event) {
+                    Some(line) => line,
+                    None => return ControlFlow::Continue(()),
+                },
+            };
+            let last = line == "ended slot-1";
+            let _ = tx.send(line);
+            if last { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        })
+        .await;
+    });
+
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    conn.send(book(UP, &[("0.40", "100")], &[("0.45", "50")])).await;
+    assert_eq!(rx.recv().await.unwrap(), "started slot-1");
+    clock.advance(Duration::from_secs(61));
+
+    // Inden for vinduet: udløb meldes én gang, derefter fortsætter opdateringerne
+    assert_eq!(tokio::time::timeout(WAIT, rx.recv()).await.unwrap().unwrap(), "expired slot-1");
+    conn.send(price_change(UP, "BUY", "0.41", "100")).await;
+    // Tick'et melder hvert sekund, så der samles over et fast tidsrum frem for til stilhed
+    let window = tokio::time::Instant::now() + Duration::from_millis(2500);
+    let mut seen = Vec::new();
+    while let Ok(Some(line)) = tokio::time::timeout_at(window, rx.recv()).await {
+        seen.push(line);
+    }
+    assert!(seen.len() >= 2, "{:?}", seen);
+    assert!(seen.iter().all(|line| line == "expired update"), "{:?}", seen);
+
+    // Vinduet slutter først når uret passerer end_ts + post_expiry_observe_secs
+    clock.advance(Duration::from_secs(30));
+    let mut seen = Vec::new();
+    while let Ok(Some(line)) = tokio::time::timeout(WAIT, rx.recv()).await {
+        seen.push(line);
+    }
+    assert_eq!(seen.last().map(String::as_str), Some("ended slot-1"));
+    tokio::time::timeout(WAIT, session).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn a_market_that_never_sends_a_book_is_given_up_for_the_next_slot() {
+    let mut server = MockServer::start().await;
+    let end_ts = START_MS / 1000 + 600;
+    let config = SessionConfig {
+        orderbook: builder(server.url()).clock(Arc::new(MockClock::new(START_MS))).build().unwrap(),
+        first_data_deadline: Some(Duration::from_millis(300)),
+        retry_delay: Duration::from_millis(10),
+        ..Default::default()
+    };
+    let mut markets = vec![market("slot-1", end_ts, UP, DOWN), market("slot-2", end_ts + 900, "333", "444")].into_iter();
+    let discover = move || {
+        let next = markets.next();
+        async move { next.ok_or(DiscoveryError::NoActiveSlot { tried: Vec::new() }) }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let session = tokio::spawn(async move {
+        session::run_session(discover, &config, |event| {
+            let Some(line) = describe(&event) else { return ControlFlow::Continue(()) };
+            let last = line == "started slot-2";
+            let _ = tx.send(line);
+            if last { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        })
+        .await;
+    });
+
+    // Forbundet og subscribed, men ingen bog - handlen lukkes efter fristen
+    let mut conn = server.accept().await;
+    conn.expect_subscribe(&[UP, DOWN]).await;
+    let mut seen = Vec::new();
+    while let Ok(Some(line)) = tokio::time::timeout(WAIT, rx.recv()).await {
+        seen.push(line);
+    }
+    assert_eq!(seen, ["started slot-1", "no data slot-1", "ended slot-1", "started slot-2"]);
+    assert_eq!(conn.recv().await["type"], "unsubscribe");
+    assert_eq!(conn.try_recv(WAIT).await, None);
+    tokio::time::timeout(WAIT, session).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn refresh_markets_rolls_new_slots_and_closes_gone_ones() {
+    let mut server = MockServer::start().await;
+    let found = Arc::new(std::sync::Mutex::new(vec![
+        Some(market("btc-1", 0, UP, DOWN)),
+        Some(market("eth-1", 0, "333", "444")),
+    ]));
+    let discover = {
+        let found = found.clone();
+        move |series: usize| {
+            let next = found.lock().unwrap()[series].clone();
+            async move { next.ok_or(DiscoveryError::NoActiveSlot { tried: Vec::new() }) }
+        }
+    };
+    let mut manager = MarketManager::new(2, config(server.url()), discover);
+    let mut events = manager.subscribe_events();
+    let slugs = |manager: &MarketManager<_>| manager.markets().iter().map(|m| m.slug.clone()).collect::<Vec<_>>();
+    let describe = |event: ManagerEvent| match event {
+        ManagerEvent::MarketRolled { series, from, to } => format!("{} rolled {:?} -> {}", series, from.map(|m| m.slug), to.slug),
+        ManagerEvent::MarketClosed { series, market } => format!("{} closed {}", series, market.slug),
+    };
+
+    // Første kald starter et orderbook-lag pr. serie
+    manager.refresh_markets().await;
+    assert_eq!(slugs(&manager), ["btc-1", "eth-1"]);
+    assert_eq!(describe(events.try_recv().unwrap()), "0 rolled None -> btc-1");
+    assert_eq!(describe(events.try_recv().unwrap()), "1 rolled None -> eth-1");
+    server.accept().await.expect_subscribe(&[UP, DOWN]).await;
+    server.accept().await.expect_subscribe(&["333", "444"]).await;
+
+    // Uændret discovery gør intet
+    manager.refresh_markets().await;
+    assert!(events.try_recv().is_err());
+    assert!(server.try_accept(Duration::from_millis(100)).await.is_none());
+
+    // BTC ruller til næste slot, ETH har intet aktivt slot længere
+    *found.lock().unwrap() = vec![Some(market("btc-2", 0, "555", "666")), None];
+    manager.refresh_markets().await;
+    assert_eq!(slugs(&manager), ["btc-2"]);
+    assert_eq!(describe(events.try_recv().unwrap()), "0 rolled Some(\"btc-1\") -> btc-2");
+    assert_eq!(describe(events.try_recv().unwrap()), "1 closed eth-1");
+    server.accept().await.expect_subscribe(&["555", "666"]).await;
+    assert_eq!(manager.handle(0).unwrap().get_current_state().await.up_bid_price, None);
+    assert!(manager.handle(1).is_none());
+
+    manager.shutdown().await;
+}